@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use flate2::read::GzDecoder;
+use crate::error::{Error, Result};
+
+/// Joins an archive's path to the name of an entry inside it: "backup.zip!/DCIM/IMG_1.jpg"
+pub const ENTRY_SEPARATOR: &str = "!/";
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn format_of(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".zip") {
+        Some(Format::Zip)
+    } else if name.ends_with(".tar") {
+        Some(Format::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Format::TarGz)
+    } else {
+        None
+    }
+}
+
+/// Whether the scanner can look inside this file
+pub fn is_archive(path: &Path) -> bool {
+    format_of(path).is_some()
+}
+
+/// Virtual path of an entry, as cataloged
+pub fn entry_path(archive: &Path, entry: &str) -> String {
+    format!("{}{ENTRY_SEPARATOR}{entry}", archive.to_string_lossy())
+}
+
+/// Split a virtual path into the archive and the entry name. None for ordinary
+/// paths, including ones that merely contain "!/" in a folder name.
+pub fn split_entry_path(path: &str) -> Option<(&Path, &str)> {
+    path.match_indices(ENTRY_SEPARATOR)
+        .map(|(at, _)| (Path::new(&path[..at]), &path[at + ENTRY_SEPARATOR.len()..]))
+        .find(|(archive, _)| is_archive(archive))
+}
+
+/// Where an entry goes when staged under `root`. None for names that would
+/// escape it ("../x", "/etc/x").
+pub fn staged_path(root: &Path, entry: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for component in Path::new(entry).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (path != root).then_some(path)
+}
+
+/// Call `visit` with the name, modification time and contents of each regular
+/// file in the archive, in archive order, until it returns false
+pub fn for_each_file(
+    archive: &Path,
+    mut visit: impl FnMut(&str, Option<DateTime<Utc>>, &mut dyn Read) -> io::Result<bool>,
+) -> Result<()> {
+    let format = format_of(archive).ok_or_else(|| Error::Invalid(format!("Not a ZIP or TAR archive: {}", archive.display())))?;
+    let file = fs::File::open(archive)?;
+
+    if let Format::Zip = format {
+        let mut zip = zip::ZipArchive::new(file)?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i)?;
+            if !entry.is_file() {
+                continue;
+            }
+            let name = entry.name().to_string();
+            let modified = entry.last_modified().and_then(zip_time);
+            if !visit(&name, modified, &mut entry)? {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let reader: Box<dyn Read> = match format {
+        Format::TarGz => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().to_string();
+        let modified = entry.header().mtime().ok().and_then(|secs| DateTime::from_timestamp(secs as i64, 0));
+        if !visit(&name, modified, &mut entry)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Write each wanted entry to its destination in a single pass over the archive,
+/// keeping modification times. Returns the names of the entries found.
+pub fn extract_entries(archive: &Path, destinations: &HashMap<String, PathBuf>) -> Result<HashSet<String>> {
+    let mut written = HashSet::new();
+    for_each_file(archive, |name, modified, reader| {
+        if let Some(destination) = destinations.get(name) {
+            let mut file = fs::File::create(destination)?;
+            io::copy(reader, &mut file)?;
+            if let Some(modified) = modified {
+                file.set_modified(modified.into())?;
+            }
+            written.insert(name.to_string());
+        }
+        Ok(written.len() < destinations.len())
+    })?;
+    Ok(written)
+}
+
+/// ZIP stores local wall-clock time with no zone
+fn zip_time(time: zip::DateTime) -> Option<DateTime<Utc>> {
+    let local = NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+        .and_hms_opt(time.hour().into(), time.minute().into(), time.second().into())?;
+    Local.from_local_datetime(&local).earliest().map(|d| d.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+pub mod test_support {
+    use std::io::Write;
+    use std::path::Path;
+    use zip::write::SimpleFileOptions;
+
+    /// Write a ZIP holding the given (name, contents) entries
+    pub fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        for (name, data) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_support::write_zip;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_entry_paths() {
+        let path = entry_path(Path::new("/photos/Wow!/takeout.zip"), "Takeout/IMG_1.jpg");
+        assert_eq!(path, "/photos/Wow!/takeout.zip!/Takeout/IMG_1.jpg");
+        assert_eq!(split_entry_path(&path), Some((Path::new("/photos/Wow!/takeout.zip"), "Takeout/IMG_1.jpg")));
+        assert_eq!(split_entry_path("/photos/Wow!/IMG_1.jpg"), None);
+
+        let root = Path::new("/tmp/stage");
+        assert_eq!(staged_path(root, "DCIM/./IMG_1.jpg"), Some(root.join("DCIM/IMG_1.jpg")));
+        assert_eq!(staged_path(root, "../IMG_1.jpg"), None);
+        assert_eq!(staged_path(root, "/etc/passwd"), None);
+    }
+
+    #[test]
+    fn test_extract_entries() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("backup.zip");
+        write_zip(&archive, &[("a.txt", b"first"), ("DCIM/IMG_1.jpg", b"photo")]);
+
+        let destination = dir.path().join("IMG_1.jpg");
+        let destinations = HashMap::from([
+            ("DCIM/IMG_1.jpg".to_string(), destination.clone()),
+            ("missing.jpg".to_string(), dir.path().join("missing.jpg")),
+        ]);
+        assert_eq!(extract_entries(&archive, &destinations).unwrap(), HashSet::from(["DCIM/IMG_1.jpg".to_string()]));
+        assert_eq!(fs::read(&destination).unwrap(), b"photo");
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::Path;
+use rayon::prelude::*;
+use crate::quality::sharpness_of;
+use crate::similarity::{hamming_distance, parse_hash};
+use crate::types::*;
+
+/// Default longest gap between consecutive frames of one burst
+pub const DEFAULT_MAX_GAP_SECONDS: i64 = 2;
+
+/// Default perceptual distance between consecutive frames. Looser than
+/// near-duplicate matching, since the subject moves between frames.
+pub const DEFAULT_BURST_DISTANCE: u32 = 16;
+
+/// Group photos taken in quick succession on the same camera into bursts
+/// and score every frame, so the sharpest one can be kept
+pub fn find_bursts(files: &[FileMetadata], max_gap_seconds: i64, max_distance: u32) -> Vec<Burst> {
+    group_bursts(files, max_gap_seconds, max_distance)
+        .into_iter()
+        .map(|members| {
+            let frames: Vec<BurstFrame> = members.into_par_iter()
+                .map(|file| BurstFrame {
+                    // Scanned photos already carry a score; older catalog entries don't
+                    sharpness: file.sharpness.map(f64::from)
+                        .or_else(|| sharpness_of(Path::new(&file.path))),
+                    file: file.clone(),
+                })
+                .collect();
+
+            let sharpest = frames.iter()
+                .filter_map(|frame| Some((frame, frame.sharpness?)))
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(frame, _)| frame.file.path.clone());
+
+            Burst {
+                camera_model: frames[0].file.camera_model.clone(),
+                started_at: frames[0].file.date_taken.unwrap_or_default(),
+                frames,
+                sharpest,
+            }
+        })
+        .collect()
+}
+
+/// Runs of images, in capture order, where each frame follows the previous one
+/// within `max_gap_seconds`, on the same camera, and looks alike
+fn group_bursts(files: &[FileMetadata], max_gap_seconds: i64, max_distance: u32) -> Vec<Vec<&FileMetadata>> {
+    // Only a real capture time can place frames in a burst; a file's mtime can't,
+    // and neither can a date guessed from a name or folder.
+    let mut photos: Vec<&FileMetadata> = files.iter()
+        .filter(|f| matches!(f.file_type, FileType::Image))
+        .filter(|f| f.date_taken.is_some() && f.date_taken_offset.is_some())
+        .filter(|f| !matches!(f.date_source, Some(DateSource::Takeout | DateSource::FileName | DateSource::Folder)))
+        .collect();
+    photos.sort_by(|a, b| a.camera_model.cmp(&b.camera_model)
+        .then(a.date_taken.cmp(&b.date_taken))
+        .then(a.path.cmp(&b.path)));
+
+    let mut bursts: Vec<Vec<&FileMetadata>> = Vec::new();
+    let mut current: Vec<&FileMetadata> = Vec::new();
+
+    for photo in photos {
+        let continues = current.last().is_some_and(|prev| {
+            let gap = photo.date_taken.zip(prev.date_taken)
+                .map(|(this, prev)| (this - prev).num_seconds());
+            prev.camera_model == photo.camera_model
+                && gap.is_some_and(|gap| gap <= max_gap_seconds)
+                && looks_alike(prev, photo, max_distance)
+        });
+
+        if !continues && current.len() > 1 {
+            bursts.push(std::mem::take(&mut current));
+        } else if !continues {
+            current.clear();
+        }
+        current.push(photo);
+    }
+    if current.len() > 1 {
+        bursts.push(current);
+    }
+
+    bursts.sort_by(|a, b| a[0].date_taken.cmp(&b[0].date_taken));
+    bursts
+}
+
+/// Frames without a perceptual hash get the benefit of the doubt
+fn looks_alike(a: &FileMetadata, b: &FileMetadata, max_distance: u32) -> bool {
+    let hash = |f: &FileMetadata| f.perceptual_hash.as_deref().and_then(parse_hash);
+    match (hash(a), hash(b)) {
+        (Some(a), Some(b)) => hamming_distance(a, b) <= max_distance,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn test_group_bursts() {
+        let start = Utc.with_ymd_and_hms(2023, 7, 14, 18, 30, 0).unwrap();
+        let shot = |name: &str, seconds: i64, model: &str, phash: u64| {
+            let mut file = FileMetadata::for_test(&format!("/p/{name}"), name, 10, FileType::Image);
+            file.date_taken = Some(start + Duration::seconds(seconds));
+            file.date_taken_offset = Some(120);
+            file.camera_model = Some(model.to_string());
+            file.perceptual_hash = Some(format!("{phash:016x}"));
+            file
+        };
+
+        let mut no_exif = shot("copy.jpg", 1, "Pixel 7", 0);
+        no_exif.date_taken_offset = None;
+        let mut named = shot("IMG_20230714_183001.jpg", 1, "Pixel 7", 0);
+        named.date_source = Some(DateSource::FileName);
+
+        let files = vec![
+            shot("a1.jpg", 0, "Pixel 7", 0),
+            shot("a2.jpg", 1, "Pixel 7", 0b11),
+            shot("a3.jpg", 2, "Pixel 7", 0b111),
+            shot("other-phone.jpg", 1, "iPhone 14", 0),
+            shot("later.jpg", 10, "Pixel 7", 0),
+            shot("new-scene.jpg", 11, "Pixel 7", u64::MAX),
+            no_exif,
+            named,
+        ];
+
+        let bursts = group_bursts(&files, DEFAULT_MAX_GAP_SECONDS, DEFAULT_BURST_DISTANCE);
+        assert_eq!(bursts.len(), 1);
+        let names: Vec<&str> = bursts[0].iter().map(|f| f.file_name.as_str()).collect();
+        assert_eq!(names, vec!["a1.jpg", "a2.jpg", "a3.jpg"]);
+    }
+}
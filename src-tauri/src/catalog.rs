@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, MAIN_SEPARATOR};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::types::*;
+
+/// Shared handle to the catalog, managed by Tauri
+pub type CatalogState = Mutex<Catalog>;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS files (
+        path        TEXT PRIMARY KEY,
+        hash        TEXT,
+        file_size   INTEGER NOT NULL,
+        modified_at TEXT,
+        metadata    TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_files_hash ON files (hash);
+
+    CREATE TABLE IF NOT EXISTS scans (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        root_path   TEXT NOT NULL,
+        scanned_at  TEXT NOT NULL,
+        total_files INTEGER NOT NULL,
+        total_size  INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_scans_root ON scans (root_path);
+
+    CREATE TABLE IF NOT EXISTS resolutions (
+        id          TEXT PRIMARY KEY,
+        resolved_at TEXT NOT NULL,
+        journal     TEXT NOT NULL,
+        files       TEXT NOT NULL
+    );
+";
+
+/// Persistent SQLite catalog of scanned files, keyed by path and hash
+pub struct Catalog {
+    conn: Connection,
+}
+
+impl Catalog {
+    /// Open (or create) the catalog database at the given path
+    pub fn open(db_path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        if let Some(parent) = db_path.as_ref().parent() {
+            // Connection::open reports a clearer error than we could if this fails
+            let _ = fs::create_dir_all(parent);
+        }
+        Self::init(Connection::open(db_path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Replace everything cataloged under `root_path` with a fresh scan
+    pub fn replace_root(&mut self, root_path: &str, files: &[FileMetadata]) -> rusqlite::Result<()> {
+        let (lower, upper) = prefix_bounds(root_path);
+        let tx = self.conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM files WHERE path = ?1 OR (path >= ?2 AND path < ?3)",
+            params![root_path, lower, upper],
+        )?;
+
+        insert_files(&tx, files)?;
+
+        let total_size: u64 = files.iter().map(|f| f.file_size).sum();
+        tx.execute(
+            "INSERT INTO scans (root_path, scanned_at, total_files, total_size) VALUES (?1, ?2, ?3, ?4)",
+            params![root_path, Utc::now().to_rfc3339(), files.len() as i64, total_size as i64],
+        )?;
+
+        tx.commit()
+    }
+
+    /// Add or refresh individual files without touching the rest of their root
+    pub fn upsert_files(&mut self, files: &[FileMetadata]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        insert_files(&tx, files)?;
+        tx.commit()
+    }
+
+    /// All cataloged files under `root_path`, sorted by path
+    pub fn files_under(&self, root_path: &str) -> rusqlite::Result<Vec<FileMetadata>> {
+        let (lower, upper) = prefix_bounds(root_path);
+        let mut stmt = self.conn.prepare(
+            "SELECT metadata FROM files WHERE path = ?1 OR (path >= ?2 AND path < ?3) ORDER BY path",
+        )?;
+        let rows = stmt.query_map(params![root_path, lower, upper], |row| row.get::<_, String>(0))?;
+
+        rows.map(|json| json.and_then(|j| from_json(&j))).collect()
+    }
+
+    /// Look up a single cataloged file by its path
+    pub fn get_file(&self, path: &str) -> rusqlite::Result<Option<FileMetadata>> {
+        self.conn
+            .query_row("SELECT metadata FROM files WHERE path = ?1", params![path], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()?
+            .map(|json| from_json(&json))
+            .transpose()
+    }
+
+    /// Re-key files (old path → new path) after they have been moved on disk,
+    /// keeping Live Photo halves and companions pointing at each other
+    pub fn update_paths(&mut self, moves: &HashMap<String, String>) -> rusqlite::Result<()> {
+        // Moved files plus whatever refers to them, by their current path
+        let mut affected: BTreeMap<String, FileMetadata> = BTreeMap::new();
+        for old_path in moves.keys() {
+            let Some(file) = self.get_file(old_path)? else {
+                continue;
+            };
+            for related in file.paired_with.iter().chain(&file.companion_of).chain(&file.companions) {
+                if !affected.contains_key(related) {
+                    if let Some(related_file) = self.get_file(related)? {
+                        affected.insert(related.clone(), related_file);
+                    }
+                }
+            }
+            affected.insert(old_path.clone(), file);
+        }
+
+        let remap = |path: &mut String| {
+            if let Some(new_path) = moves.get(path.as_str()) {
+                path.clone_from(new_path);
+            }
+        };
+
+        let tx = self.conn.transaction()?;
+        let mut changed = Vec::with_capacity(affected.len());
+        for (old_path, mut file) in affected {
+            if let Some(new_path) = moves.get(&old_path) {
+                tx.execute("DELETE FROM files WHERE path = ?1", params![old_path])?;
+                file.file_name = Path::new(new_path)
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string();
+            }
+            remap(&mut file.path);
+            file.paired_with.iter_mut()
+                .chain(file.companion_of.iter_mut())
+                .chain(file.companions.iter_mut())
+                .for_each(remap);
+            changed.push(file);
+        }
+        insert_files(&tx, &changed)?;
+        tx.commit()
+    }
+
+    /// Drop files that no longer exist at their cataloged path
+    pub fn remove_files(&mut self, paths: &[&str]) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut delete = tx.prepare_cached("DELETE FROM files WHERE path = ?1")?;
+            for path in paths {
+                delete.execute(params![path])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Write (or rewrite) a resolution journal along with the catalog entries it
+    /// affected, so undoing it can put them back
+    pub fn record_resolution(&mut self, resolution: &Resolution, files: &[FileMetadata]) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO resolutions (id, resolved_at, journal, files) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                resolution.id,
+                resolution.resolved_at.to_rfc3339(),
+                to_json(resolution)?,
+                to_json(files)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// A resolution journal and the catalog entries saved with it
+    pub fn get_resolution(&self, id: &str) -> rusqlite::Result<Option<(Resolution, Vec<FileMetadata>)>> {
+        self.conn
+            .query_row("SELECT journal, files FROM resolutions WHERE id = ?1", params![id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .optional()?
+            .map(|(journal, files)| Ok((from_json(&journal)?, from_json(&files)?)))
+            .transpose()
+    }
+
+    /// Past duplicate resolutions, most recent first
+    pub fn resolution_history(&self) -> rusqlite::Result<Vec<Resolution>> {
+        let mut stmt = self.conn.prepare("SELECT journal FROM resolutions ORDER BY resolved_at DESC")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.map(|json| json.and_then(|j| from_json(&j))).collect()
+    }
+
+    /// Past scans, most recent first
+    pub fn scan_history(&self) -> rusqlite::Result<Vec<ScanRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, root_path, scanned_at, total_files, total_size FROM scans ORDER BY id DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let scanned_at: String = row.get(2)?;
+            Ok(ScanRecord {
+                id: row.get(0)?,
+                root_path: row.get(1)?,
+                scanned_at: DateTime::parse_from_rfc3339(&scanned_at)
+                    .map(|d| d.with_timezone(&Utc))
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e)))?,
+                total_files: row.get::<_, i64>(3)? as usize,
+                total_size: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+
+        rows.collect()
+    }
+}
+
+fn insert_files(conn: &Connection, files: &[FileMetadata]) -> rusqlite::Result<()> {
+    let mut insert = conn.prepare_cached(
+        "INSERT OR REPLACE INTO files (path, hash, file_size, modified_at, metadata)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for file in files {
+        insert.execute(params![
+            file.path,
+            file.hash,
+            file.file_size as i64,
+            file.modified_at.map(|d| d.to_rfc3339()),
+            to_json(file)?,
+        ])?;
+    }
+    Ok(())
+}
+
+/// Range bounds matching every path strictly below `root_path`.
+/// The upper bound swaps the trailing separator for the next character,
+/// so the range stays index-friendly without LIKE escaping.
+fn prefix_bounds(root_path: &str) -> (String, String) {
+    let root = root_path.trim_end_matches(MAIN_SEPARATOR);
+    let next = char::from_u32(MAIN_SEPARATOR as u32 + 1).unwrap_or(MAIN_SEPARATOR);
+    (format!("{root}{MAIN_SEPARATOR}"), format!("{root}{next}"))
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn from_json<T: DeserializeOwned>(json: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_root_only_touches_that_root() {
+        let sep = MAIN_SEPARATOR;
+        let mut catalog = Catalog::open_in_memory().unwrap();
+        let image = |path: &str, hash: &str| FileMetadata::for_test(path, hash, 1024, FileType::Image);
+        let a = format!("{sep}photos{sep}a.jpg");
+        let b = format!("{sep}photos{sep}2019{sep}b.jpg");
+        let other = format!("{sep}photos-old{sep}c.jpg");
+
+        catalog.replace_root(&format!("{sep}photos-old"), &[image(&other, "c")]).unwrap();
+        catalog.replace_root(&format!("{sep}photos"), &[image(&a, "a"), image(&b, "b")]).unwrap();
+        catalog.replace_root(&format!("{sep}photos"), &[image(&a, "a")]).unwrap();
+
+        let files = catalog.files_under(&format!("{sep}photos")).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, a);
+        assert!(catalog.get_file(&other).unwrap().is_some());
+        assert_eq!(catalog.scan_history().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_update_paths_follows_companions() {
+        let mut catalog = Catalog::open_in_memory().unwrap();
+        let mut jpeg = FileMetadata::for_test("/src/IMG_1.JPG", "a", 10, FileType::Image);
+        let mut raw = FileMetadata::for_test("/src/IMG_1.CR2", "b", 10, FileType::Image);
+        jpeg.companions = vec!["/src/IMG_1.CR2".into(), "/src/IMG_1.xmp".into()];
+        raw.companion_of = Some("/src/IMG_1.JPG".into());
+        catalog.upsert_files(&[jpeg, raw]).unwrap();
+
+        let moves = HashMap::from([
+            ("/src/IMG_1.JPG".to_string(), "/dst/IMG_1_1.JPG".to_string()),
+            ("/src/IMG_1.xmp".to_string(), "/dst/IMG_1_1.xmp".to_string()),
+        ]);
+        catalog.update_paths(&moves).unwrap();
+
+        let jpeg = catalog.get_file("/dst/IMG_1_1.JPG").unwrap().unwrap();
+        assert_eq!(jpeg.file_name, "IMG_1_1.JPG");
+        assert_eq!(jpeg.companions, vec!["/src/IMG_1.CR2", "/dst/IMG_1_1.xmp"]);
+        let raw = catalog.get_file("/src/IMG_1.CR2").unwrap().unwrap();
+        assert_eq!(raw.companion_of.as_deref(), Some("/dst/IMG_1_1.JPG"));
+        assert!(catalog.get_file("/src/IMG_1.JPG").unwrap().is_none());
+    }
+}
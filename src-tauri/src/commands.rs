@@ -1,8 +1,21 @@
-use crate::scanner::Scanner;
+use crate::scanner::{compute_stats, Scanner};
 use crate::organizer::generate_organization_plan;
 use crate::executor::execute_organization_plan;
+use crate::catalog::CatalogState;
+use crate::similarity::{group_similar, DEFAULT_MAX_DISTANCE};
+use crate::duplicates;
+use crate::downloads::DownloadRules;
+use crate::bursts::{find_bursts, DEFAULT_BURST_DISTANCE, DEFAULT_MAX_GAP_SECONDS};
+use crate::resolver;
+use crate::error::{Error, Result};
+use crate::exif_data::ExifData;
+use crate::scan_options::ScanOptions;
+use crate::sessions::{ScanControl, ScanSessions};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
 use crate::types::*;
-use tauri::AppHandle;
+use tauri::{AppHandle, State};
 
 #[tauri::command]
 pub fn greet(name: &str) -> String {
@@ -10,14 +23,179 @@ pub fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-pub async fn scan_directory(path: String, app_handle: AppHandle) -> Result<ScanResult, String> {
-    let scanner = Scanner::new(&path);
-    scanner.scan(&app_handle).map_err(|e| e.to_string())
+pub async fn scan_directory(
+    path: String,
+    options: Option<ScanOptions>,
+    session_id: Option<String>,
+    app_handle: AppHandle,
+    catalog: State<'_, CatalogState>,
+    sessions: State<'_, ScanSessions>,
+) -> Result<ScanResult> {
+    let scanner = Scanner::new(&path).options(options.unwrap_or_default())?;
+    let control = sessions.start(session_id);
+    let scanner = scanner.with_control(control.clone());
+
+    let result = scanner.scan(&app_handle, &catalog);
+
+    sessions.finish(control.id());
+    result
+}
+
+#[tauri::command]
+pub fn cancel_scan(session_id: String, sessions: State<'_, ScanSessions>) -> Result<()> {
+    find_session(&sessions, &session_id)?.cancel();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn pause_scan(session_id: String, sessions: State<'_, ScanSessions>) -> Result<()> {
+    find_session(&sessions, &session_id)?.pause();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn resume_scan(session_id: String, sessions: State<'_, ScanSessions>) -> Result<()> {
+    find_session(&sessions, &session_id)?.resume();
+    Ok(())
+}
+
+fn find_session(sessions: &ScanSessions, session_id: &str) -> Result<Arc<ScanControl>> {
+    sessions.get(session_id)
+        .ok_or_else(|| Error::SessionNotFound(session_id.to_string()))
+}
+
+#[tauri::command]
+pub fn get_scan_stats(root_path: String, catalog: State<'_, CatalogState>) -> Result<ScanStats> {
+    let catalog = catalog.lock()?;
+    let files = catalog.files_under(&root_path)?;
+    Ok(compute_stats(&files))
+}
+
+/// Re-tag cataloged files with (possibly user-edited) download rules and return fresh stats
+#[tauri::command]
+pub fn classify_downloads(
+    root_path: String,
+    rules: Option<DownloadRules>,
+    catalog: State<'_, CatalogState>,
+) -> Result<ScanStats> {
+    let rules = rules.unwrap_or_default();
+    let mut catalog = catalog.lock()?;
+    let mut files = catalog.files_under(&root_path)?;
+
+    for file in files.iter_mut() {
+        rules.classify(file);
+    }
+
+    catalog.upsert_files(&files)?;
+    Ok(compute_stats(&files))
+}
+
+#[tauri::command]
+pub fn get_default_download_rules() -> DownloadRules {
+    DownloadRules::default()
+}
+
+#[tauri::command]
+pub fn get_scan_history(catalog: State<'_, CatalogState>) -> Result<Vec<ScanRecord>> {
+    let catalog = catalog.lock()?;
+    Ok(catalog.scan_history()?)
+}
+
+#[tauri::command]
+pub fn get_file_details(path: String, catalog: State<'_, CatalogState>) -> Result<FileDetails> {
+    let metadata = {
+        let catalog = catalog.lock()?;
+        catalog.get_file(&path)?
+    };
+    // Unreadable EXIF still leaves the cataloged metadata worth showing
+    let exif = ExifData::read(Path::new(&path))
+        .ok()
+        .flatten()
+        .map(|e| e.all_fields())
+        .unwrap_or_default();
+
+    Ok(FileDetails { metadata, exif })
+}
+
+#[tauri::command]
+pub fn find_similar_images(
+    root_path: String,
+    max_distance: Option<u32>,
+    catalog: State<'_, CatalogState>,
+) -> Result<Vec<SimilarGroup>> {
+    let files = {
+        let catalog = catalog.lock()?;
+        catalog.files_under(&root_path)?
+    };
+    Ok(group_similar(&files, max_distance.unwrap_or(DEFAULT_MAX_DISTANCE)))
+}
+
+/// Group exact and near-identical copies under a root, suggest a keeper for each
+/// group and persist `is_duplicate` on the rest
+#[tauri::command]
+pub fn find_duplicate_groups(
+    root_path: String,
+    rule: Option<KeeperRule>,
+    include_similar: Option<bool>,
+    max_distance: Option<u32>,
+    catalog: State<'_, CatalogState>,
+) -> Result<Vec<DuplicateGroup>> {
+    let mut catalog = catalog.lock()?;
+    let mut files = catalog.files_under(&root_path)?;
+
+    let max_distance = include_similar.unwrap_or(true)
+        .then(|| max_distance.unwrap_or(DEFAULT_MAX_DISTANCE));
+    let rule = rule.unwrap_or(KeeperRule::HighestResolution);
+    let groups = duplicates::find_duplicate_groups(&mut files, max_distance, &rule);
+
+    catalog.upsert_files(&files)?;
+    Ok(groups)
+}
+
+/// Group bursts of near-identical shots and point out the sharpest frame of each
+#[tauri::command]
+pub async fn find_photo_bursts(
+    root_path: String,
+    max_gap_seconds: Option<i64>,
+    max_distance: Option<u32>,
+    catalog: State<'_, CatalogState>,
+) -> Result<Vec<Burst>> {
+    let files = {
+        let catalog = catalog.lock()?;
+        catalog.files_under(&root_path)?
+    };
+    Ok(find_bursts(
+        &files,
+        max_gap_seconds.unwrap_or(DEFAULT_MAX_GAP_SECONDS),
+        max_distance.unwrap_or(DEFAULT_BURST_DISTANCE),
+    ))
+}
+
+/// Trash, quarantine or hardlink the non-keepers of each group. Journaled so it can be undone.
+#[tauri::command]
+pub fn resolve_duplicates(
+    groups: Vec<DuplicateGroup>,
+    method: ResolutionMethod,
+    quarantine_dir: Option<String>,
+    catalog: State<'_, CatalogState>,
+) -> Result<Resolution> {
+    let mut catalog = catalog.lock()?;
+    resolver::resolve_duplicates(&mut catalog, &groups, method, quarantine_dir.as_deref())
 }
 
 #[tauri::command]
-pub fn get_scan_stats(result: ScanResult) -> ScanStats {
-    result.stats
+pub fn undo_resolution(
+    resolution_id: String,
+    catalog: State<'_, CatalogState>,
+) -> Result<Resolution> {
+    let mut catalog = catalog.lock()?;
+    resolver::undo_resolution(&mut catalog, &resolution_id)
+}
+
+#[tauri::command]
+pub fn get_resolution_history(catalog: State<'_, CatalogState>) -> Result<Vec<Resolution>> {
+    let catalog = catalog.lock()?;
+    Ok(catalog.resolution_history()?)
 }
 
 // ============================================================================
@@ -26,20 +204,38 @@ pub fn get_scan_stats(result: ScanResult) -> ScanStats {
 
 #[tauri::command]
 pub async fn create_organization_plan(
-    files: Vec<FileMetadata>,
+    source_root: String,
     destination_root: String,
     strategy: OrganizationStrategy,
     mode: OperationMode,
-) -> Result<OrganizationPlan, String> {
-    generate_organization_plan(files, destination_root, strategy, mode)
+    catalog: State<'_, CatalogState>,
+) -> Result<OrganizationPlan> {
+    let files = {
+        let catalog = catalog.lock()?;
+        catalog.files_under(&source_root)?
+    };
+    generate_organization_plan(files, source_root, destination_root, strategy, mode)
 }
 
 #[tauri::command]
 pub async fn execute_organization(
     plan: OrganizationPlan,
-    source_files: Vec<FileMetadata>,
-) -> Result<OperationResult, String> {
-    execute_organization_plan(plan, source_files)
+    catalog: State<'_, CatalogState>,
+) -> Result<OperationResult> {
+    let source_files = catalog.lock()?.files_under(&plan.source_root)?;
+    let is_move = matches!(plan.mode, OperationMode::Move);
+    let result = execute_organization_plan(plan, source_files)?;
+
+    // Keep the catalog pointing at files that were moved
+    if is_move {
+        let moves: HashMap<String, String> = result.operations.iter()
+            .filter(|op| matches!(op.status, OperationStatus::Success))
+            .map(|op| (op.source_path.clone(), op.destination_path.clone()))
+            .collect();
+        catalog.lock()?.update_paths(&moves)?;
+    }
+
+    Ok(result)
 }
 
 // ============================================================================
@@ -47,20 +243,19 @@ pub async fn execute_organization(
 // ============================================================================
 
 #[tauri::command]
-pub fn get_home_dir() -> Result<String, String> {
+pub fn get_home_dir() -> Result<String> {
     use std::env;
     env::var("HOME")
         .or_else(|_| env::var("USERPROFILE"))
-        .map_err(|e| format!("Failed to get home directory: {e}"))
+        .map_err(|e| Error::Invalid(format!("Failed to get home directory: {e}")))
 }
 
 #[tauri::command]
-pub fn list_directory(path: String) -> Result<Vec<serde_json::Value>, String> {
+pub fn list_directory(path: String) -> Result<Vec<serde_json::Value>> {
     use std::fs;
     use std::time::SystemTime;
     
-    let entries = fs::read_dir(&path)
-        .map_err(|e| format!("Failed to read directory: {e}"))?;
+    let entries = fs::read_dir(&path)?;
     
     let mut items = Vec::new();
     
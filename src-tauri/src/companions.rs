@@ -0,0 +1,124 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use crate::detect::is_raw_extension;
+use crate::types::*;
+
+/// Edit sidecars written next to a photo: XMP (Lightroom, darktable, Capture One),
+/// AAE (Apple Photos), DOP (DxO), PP3 (RawTherapee), ON1
+const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "aae", "dop", "pp3", "on1"];
+
+/// Tie together files that belong to one shot. In a RAW+JPEG pair the developed
+/// image leads and the RAW points back at it through `companion_of`. Sidecars
+/// ("IMG.xmp" or "IMG.CR2.xmp") aren't media, so `walked` lists every scanned
+/// path to find them. Each lead's `companions` lists its RAW and all sidecars.
+pub fn link_companions<'a>(files: &mut [FileMetadata], walked: impl IntoIterator<Item = &'a Path>) {
+    for file in files.iter_mut() {
+        file.companions.clear();
+        file.companion_of = None;
+    }
+
+    let lower = |s: &std::ffi::OsStr| s.to_string_lossy().to_lowercase();
+    let by_name: HashMap<(PathBuf, String), &Path> = walked.into_iter()
+        .filter_map(|p| Some(((p.parent()?.to_path_buf(), lower(p.file_name()?)), p)))
+        .collect();
+
+    // RAW+JPEG: same folder, same name, one RAW and one developed image
+    let mut shots: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (i, file) in files.iter().enumerate().filter(|(_, f)| matches!(f.file_type, FileType::Image)) {
+        let path = Path::new(&file.path);
+        if let (Some(parent), Some(stem)) = (path.parent(), path.file_stem()) {
+            shots.entry((parent.to_path_buf(), lower(stem))).or_default().push(i);
+        }
+    }
+    let is_raw = |file: &FileMetadata| {
+        Path::new(&file.path).extension().is_some_and(|e| is_raw_extension(&lower(e)))
+    };
+    for members in shots.values() {
+        let (raws, developed): (Vec<usize>, Vec<usize>) = members.iter().partition(|&&i| is_raw(&files[i]));
+        let Some(&lead) = developed.first() else {
+            continue;
+        };
+        for raw in raws {
+            files[raw].companion_of = Some(files[lead].path.clone());
+            let raw_path = files[raw].path.clone();
+            files[lead].companions.push(raw_path);
+        }
+    }
+
+    // Sidecars go to whoever leads the shot; images claim a shared one before videos
+    let index: HashMap<String, usize> = files.iter().enumerate().map(|(i, f)| (f.path.clone(), i)).collect();
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| !matches!(files[i].file_type, FileType::Image));
+    let mut claimed: HashSet<&Path> = HashSet::new();
+
+    for i in order {
+        let path = Path::new(&files[i].path);
+        let (Some(parent), Some(name), Some(stem)) = (path.parent(), path.file_name(), path.file_stem()) else {
+            continue;
+        };
+        let (name, stem) = (lower(name), lower(stem));
+        let sidecars: Vec<&Path> = SIDECAR_EXTENSIONS.iter()
+            .flat_map(|ext| [format!("{stem}.{ext}"), format!("{name}.{ext}")])
+            .filter_map(|n| by_name.get(&(parent.to_path_buf(), n)).copied())
+            .filter(|p| claimed.insert(p))
+            .collect();
+
+        let lead = files[i].companion_of.as_ref().and_then(|l| index.get(l)).copied().unwrap_or(i);
+        files[lead].companions.extend(sidecars.into_iter().map(|p| p.to_string_lossy().to_string()));
+    }
+
+    for file in files.iter_mut() {
+        file.companions.sort();
+    }
+}
+
+/// Name for a companion moving with its lead: the part matching the lead's old
+/// stem becomes the new stem ("IMG_1.CR2.xmp" → "IMG_1_1.CR2.xmp")
+pub fn companion_name(companion: &Path, old_lead: &Path, new_lead: &Path) -> String {
+    let name = companion.file_name().unwrap_or_default().to_string_lossy();
+    let old_stem = old_lead.file_stem().unwrap_or_default().to_string_lossy();
+    let new_stem = new_lead.file_stem().unwrap_or_default().to_string_lossy();
+    match name.get(..old_stem.len()) {
+        Some(prefix) if prefix.eq_ignore_ascii_case(&old_stem) => format!("{new_stem}{}", &name[old_stem.len()..]),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_companions() {
+        let mut files = vec![
+            FileMetadata::for_test("/p/IMG_1.CR2", "", 10, FileType::Image),
+            FileMetadata::for_test("/p/IMG_1.JPG", "", 10, FileType::Image),
+            FileMetadata::for_test("/p/IMG_2.NEF", "", 10, FileType::Image),
+            FileMetadata::for_test("/p/IMG_3.HEIC", "", 10, FileType::Image),
+            FileMetadata::for_test("/p/IMG_3.MOV", "", 10, FileType::Video),
+        ];
+        let walked = [
+            "/p/IMG_1.CR2", "/p/IMG_1.CR2.xmp", "/p/IMG_1.JPG", "/p/IMG_1.dop",
+            "/p/IMG_2.NEF", "/p/IMG_2.xmp", "/p/IMG_3.AAE", "/p/IMG_3.HEIC", "/p/IMG_3.MOV", "/p/notes.xmp",
+        ];
+        link_companions(&mut files, walked.iter().map(Path::new));
+
+        let companions: Vec<Vec<&str>> = files.iter()
+            .map(|f| f.companions.iter().map(String::as_str).collect())
+            .collect();
+        assert_eq!(companions, vec![
+            vec![],
+            vec!["/p/IMG_1.CR2", "/p/IMG_1.CR2.xmp", "/p/IMG_1.dop"],
+            vec!["/p/IMG_2.xmp"],
+            vec!["/p/IMG_3.AAE"],
+            vec![],
+        ]);
+        assert_eq!(files[0].companion_of.as_deref(), Some("/p/IMG_1.JPG"));
+        assert!(files[2].companion_of.is_none());
+
+        assert_eq!(
+            companion_name(Path::new("/p/IMG_1.CR2.xmp"), Path::new("/p/IMG_1.JPG"), Path::new("/d/IMG_1_1.JPG")),
+            "IMG_1_1.CR2.xmp",
+        );
+    }
+}
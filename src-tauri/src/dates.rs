@@ -0,0 +1,258 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use crate::exif_data::HomeTimezone;
+use crate::types::DateSource;
+
+/// A capture time and where it came from
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedDate {
+    pub date: DateTime<Utc>,
+    pub offset: Option<i32>,    // Minutes east of UTC; the home zone's when the source has none
+    pub source: DateSource,
+}
+
+impl ResolvedDate {
+    /// From a date whose offset is known, e.g. EXIF with OffsetTime
+    pub fn local(date: DateTime<FixedOffset>, source: DateSource) -> Self {
+        Self {
+            date: date.with_timezone(&Utc),
+            offset: Some(date.offset().local_minus_utc() / 60),
+            source,
+        }
+    }
+}
+
+/// Best available capture time: the file's own metadata (`embedded`), then a Google
+/// Takeout JSON sidecar, an XMP sidecar, a date in the file name, and finally the
+/// folder names above it. None when nothing dates the file.
+pub fn resolve_date(path: &Path, embedded: Option<ResolvedDate>, home: &HomeTimezone) -> Option<ResolvedDate> {
+    // Wall-clock times without an offset are read in the home timezone
+    let guessed = |local: NaiveDateTime, source| ResolvedDate::local(home.localize(local), source);
+
+    embedded
+        .or_else(|| takeout_date(path, home))
+        .or_else(|| xmp_date(path, home))
+        .or_else(|| {
+            let stem = path.file_stem()?.to_string_lossy();
+            date_in_name(&stem).map(|local| guessed(local, DateSource::FileName))
+        })
+        .or_else(|| folder_date(path).map(|local| guessed(local, DateSource::Folder)))
+}
+
+/// `photoTakenTime` from the JSON that Google Takeout writes next to each photo
+fn takeout_date(path: &Path, home: &HomeTimezone) -> Option<ResolvedDate> {
+    let json: serde_json::Value = takeout_sidecars(path)
+        .into_iter()
+        .find_map(|sidecar| serde_json::from_slice(&fs::read(sidecar).ok()?).ok())?;
+
+    let timestamp = &json["photoTakenTime"]["timestamp"];
+    let seconds = timestamp.as_str().and_then(|s| s.parse().ok()).or_else(|| timestamp.as_i64())?;
+    // Takeout stores UTC only; the local offset is lost, so show it on the home clock
+    let date = DateTime::from_timestamp(seconds, 0).filter(|_| seconds > 0)?;
+    Some(ResolvedDate::local(home.at(date), DateSource::Takeout))
+}
+
+/// Takeout's sidecar naming: "IMG.jpg.json", the newer "IMG.jpg.supplemental-metadata.json",
+/// "IMG.json", and "IMG.jpg(1).json" for the second "IMG(1).jpg"
+fn takeout_sidecars(path: &Path) -> Vec<PathBuf> {
+    let (Some(name), Some(stem)) = (path.file_name(), path.file_stem()) else {
+        return Vec::new();
+    };
+    let (name, stem) = (name.to_string_lossy(), stem.to_string_lossy());
+    let mut names = vec![
+        format!("{name}.json"),
+        format!("{name}.supplemental-metadata.json"),
+        format!("{stem}.json"),
+    ];
+
+    if let Some((base, counter)) = stem.strip_suffix(')').and_then(|s| s.rsplit_once('(')) {
+        let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+        names.push(format!("{base}{extension}({counter}).json"));
+    }
+
+    names.into_iter().map(|n| path.with_file_name(n)).filter(|p| p.is_file()).collect()
+}
+
+/// Capture date from an XMP sidecar ("IMG.xmp" or "IMG.jpg.xmp"), as written by
+/// Lightroom, darktable and friends
+fn xmp_date(path: &Path, home: &HomeTimezone) -> Option<ResolvedDate> {
+    let (name, stem) = (path.file_name()?.to_string_lossy(), path.file_stem()?.to_string_lossy());
+    let xmp = [format!("{stem}.xmp"), format!("{stem}.XMP"), format!("{name}.xmp")]
+        .into_iter()
+        .find_map(|n| fs::read_to_string(path.with_file_name(n)).ok())?;
+
+    ["exif:DateTimeOriginal", "photoshop:DateCreated", "xmp:CreateDate"]
+        .into_iter()
+        .find_map(|tag| parse_xmp_date(xmp_value(&xmp, tag)?, home))
+}
+
+/// A property's value, whether written as an attribute or as an element
+fn xmp_value<'a>(xmp: &'a str, tag: &str) -> Option<&'a str> {
+    if let Some(start) = xmp.find(&format!("{tag}=\"")) {
+        let value = &xmp[start + tag.len() + 2..];
+        return value.split('"').next();
+    }
+    let start = xmp.find(&format!("<{tag}>"))? + tag.len() + 2;
+    xmp[start..].split('<').next().map(str::trim)
+}
+
+/// ISO 8601 as used in XMP, with or without an offset and fractional seconds
+fn parse_xmp_date(value: &str, home: &HomeTimezone) -> Option<ResolvedDate> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(ResolvedDate::local(date, DateSource::Xmp));
+    }
+    let local = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .ok()?;
+    // No offset: read it in the home timezone, like EXIF
+    Some(ResolvedDate::local(home.localize(local), DateSource::Xmp))
+}
+
+/// Date (and time, if present) written into a name by cameras, phones and apps:
+/// IMG_20190703_142233, PXL_20190703_142233123, IMG-20190703-WA0001,
+/// Screenshot_2019-07-03-14-22-33, "Screenshot 2019-07-03 at 14.22.33", 20190703142233
+fn date_in_name(name: &str) -> Option<NaiveDateTime> {
+    let runs: Vec<&str> = name.split(|c: char| !c.is_ascii_digit()).filter(|r| !r.is_empty()).collect();
+    let run = |i: usize, len: usize| runs.get(i).filter(|r| r.len() == len).copied();
+
+    (0..runs.len()).find_map(|i| {
+        let r = runs[i];
+        let (date, time) = if r.len() >= 14 {
+            (ymd(&r[..4], &r[4..6], &r[6..8])?, hms(&r[8..10], &r[10..12], &r[12..14]))
+        } else if r.len() == 8 {
+            (ymd(&r[..4], &r[4..6], &r[6..8])?, time_after(&runs[i + 1..]))
+        } else if r.len() == 4 {
+            (ymd(r, run(i + 1, 2)?, run(i + 2, 2)?)?, time_after(&runs[i + 3..]))
+        } else {
+            return None;
+        };
+        Some(date.and_time(time.unwrap_or_default()))
+    })
+}
+
+/// "142233", "142233123" or "14", "22", "33" right after a date
+fn time_after(runs: &[&str]) -> Option<NaiveTime> {
+    match runs {
+        [t, ..] if t.len() >= 6 => hms(&t[..2], &t[2..4], &t[4..6]),
+        [h, m, s, ..] if [h, m, s].iter().all(|r| r.len() == 2) => hms(h, m, s),
+        _ => None,
+    }
+}
+
+fn ymd(year: &str, month: &str, day: &str) -> Option<NaiveDate> {
+    let year: i32 = year.parse().ok()?;
+    if !(1900..2100).contains(&year) {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, month.parse().ok()?, day.parse().ok()?)
+}
+
+fn hms(hour: &str, minute: &str, second: &str) -> Option<NaiveTime> {
+    NaiveTime::from_hms_opt(hour.parse().ok()?, minute.parse().ok()?, second.parse().ok()?)
+}
+
+/// Date from the enclosing folders: a full date in a nearby folder name
+/// ("2019-07-03 Beach"), "2019-07 Summer", or year/month nesting ("2019/07-July").
+/// Partial dates fall on the first of the month.
+fn folder_date(path: &Path) -> Option<NaiveDateTime> {
+    let folders: Vec<String> = path.parent()?
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+
+    if let Some(date) = folders.iter().rev().take(3).find_map(|f| date_in_name(f)) {
+        return Some(date.date().and_time(NaiveTime::MIN));
+    }
+
+    let leading_digits = |s: &str| s.chars().take_while(char::is_ascii_digit).collect::<String>();
+    let year_month = |year: &str, month: &str| {
+        (year.len() == 4 && (1..=2).contains(&month.len())).then(|| ymd(year, month, "1")).flatten()
+    };
+
+    folders.iter().enumerate().rev().take(3).find_map(|(i, folder)| {
+        // "2019-07 Summer" in one folder
+        let runs: Vec<&str> = folder.split(|c: char| !c.is_ascii_digit()).collect();
+        if let [year, month, ..] = runs.as_slice() {
+            if let Some(date) = year_month(year, month) {
+                return Some(date);
+            }
+        }
+        // "2019/07-July" across two
+        let year = folders.get(i.checked_sub(1)?)?;
+        year_month(year, &leading_digits(folder))
+    })
+    .map(|date| date.and_time(NaiveTime::MIN))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FileMetadata, FileType};
+    use tempfile::TempDir;
+
+    fn name(s: &str) -> Option<String> {
+        date_in_name(s).map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+
+    #[test]
+    fn test_date_in_name() {
+        assert_eq!(name("IMG_20190703_142233").as_deref(), Some("2019-07-03 14:22:33"));
+        assert_eq!(name("PXL_20230714_183000123.MP").as_deref(), Some("2023-07-14 18:30:00"));
+        assert_eq!(name("IMG-20190703-WA0001").as_deref(), Some("2019-07-03 00:00:00"));
+        assert_eq!(name("Screenshot_2019-07-03-14-22-33").as_deref(), Some("2019-07-03 14:22:33"));
+        assert_eq!(name("Screenshot 2019-07-03 at 14.22.33").as_deref(), Some("2019-07-03 14:22:33"));
+        assert_eq!(name("20190703142233").as_deref(), Some("2019-07-03 14:22:33"));
+        assert_eq!(name("IMG_1234"), None);
+        assert_eq!(name("DSC_20191399"), None);
+    }
+
+    #[test]
+    fn test_resolve_date_fallback_chain() {
+        let dir = TempDir::new().unwrap();
+        let utc = HomeTimezone::Fixed(FixedOffset::east_opt(0).unwrap());
+        let resolve = |relative: &str| {
+            let path = dir.path().join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, b"").unwrap();
+            resolve_date(&path, None, &utc).map(|d| (d.source, d.date.to_rfc3339(), d.offset))
+        };
+
+        fs::write(dir.path().join("takeout.jpg.json"), r#"{"photoTakenTime": {"timestamp": "1562163753"}}"#).unwrap();
+        assert_eq!(resolve("takeout.jpg"), Some((DateSource::Takeout, "2019-07-03T14:22:33+00:00".into(), Some(0))));
+
+        fs::write(dir.path().join("edited.xmp"), r#"<rdf:Description exif:DateTimeOriginal="2019-07-03T14:22:33+02:00"/>"#).unwrap();
+        assert_eq!(resolve("edited.tif"), Some((DateSource::Xmp, "2019-07-03T12:22:33+00:00".into(), Some(120))));
+
+        assert_eq!(resolve("IMG-20190703-WA0001.jpg"), Some((DateSource::FileName, "2019-07-03T00:00:00+00:00".into(), Some(0))));
+        assert_eq!(resolve("2019/07-July/scan.jpg"), Some((DateSource::Folder, "2019-07-01T00:00:00+00:00".into(), Some(0))));
+        assert_eq!(resolve("Trip 2019-07-03/scan.jpg"), Some((DateSource::Folder, "2019-07-03T00:00:00+00:00".into(), Some(0))));
+        assert_eq!(resolve("misc/scan.jpg"), None);
+    }
+
+    #[test]
+    fn test_guessed_dates_keep_the_home_calendar() {
+        // Ten hours away from this machine, so its own zone would shift the day
+        let machine = chrono::Local::now().offset().local_minus_utc() / 60;
+        let home_minutes = if machine + 600 > 14 * 60 { machine - 600 } else { machine + 600 };
+        let home = HomeTimezone::from_offset_minutes(Some(home_minutes));
+
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("scan.xmp"), "<xmp:CreateDate>2019-07-03T00:30:00</xmp:CreateDate>").unwrap();
+        for (name, source) in [("scan.tif", DateSource::Xmp), ("IMG_20190703_003000.jpg", DateSource::FileName)] {
+            let path = dir.path().join(name);
+            fs::write(&path, b"").unwrap();
+            let resolved = resolve_date(&path, None, &home).unwrap();
+
+            let mut file = FileMetadata::for_test(name, "", 0, FileType::Image);
+            file.date_taken = Some(resolved.date);
+            file.date_taken_offset = resolved.offset;
+            assert_eq!(resolved.source, source);
+            assert_eq!(resolved.offset, Some(home_minutes));
+            assert_eq!(file.local_date_taken().unwrap().naive_local().to_string(), "2019-07-03 00:30:00");
+        }
+    }
+}
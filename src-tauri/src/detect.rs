@@ -0,0 +1,304 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use crate::types::FileType;
+
+/// Bytes read from the start of a file for magic-number sniffing (covers the tar header)
+const HEADER_LEN: usize = 512;
+
+/// Result of classifying a file by its content, falling back to its extension
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub file_type: FileType,
+    pub format: Option<&'static str>,   // Format recognised from content, if any
+    pub extension_mismatch: bool,       // Content says something the extension doesn't
+}
+
+/// A recognised magic number and the extensions that are honest for it
+struct Signature {
+    file_type: FileType,
+    format: &'static str,
+    extensions: &'static [&'static str],
+}
+
+impl Signature {
+    const fn new(file_type: FileType, format: &'static str, extensions: &'static [&'static str]) -> Self {
+        Self { file_type, format, extensions }
+    }
+}
+
+/// Classify a file: magic bytes first, extension as fallback
+pub fn detect_file_type(path: &Path) -> Option<Detection> {
+    let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    let by_extension = ext.as_deref().and_then(file_type_from_extension);
+
+    let header = read_header(path).unwrap_or_default();
+
+    match sniff(&header) {
+        Some(signature) => {
+            let extension_matches = ext.as_deref()
+                .is_some_and(|e| signature.extensions.contains(&e));
+
+            // Containers like ZIP back several formats (DOCX, ODT); trust the
+            // extension's type when it is one the content allows
+            let file_type = match by_extension {
+                Some(file_type) if extension_matches => file_type,
+                _ => signature.file_type,
+            };
+
+            Some(Detection {
+                file_type,
+                format: Some(signature.format),
+                extension_mismatch: ext.is_some() && !extension_matches,
+            })
+        }
+        None => by_extension.map(|file_type| Detection {
+            file_type,
+            format: None,
+            extension_mismatch: false,
+        }),
+    }
+}
+
+fn read_header(path: &Path) -> std::io::Result<Vec<u8>> {
+    let file = fs::File::open(path)?;
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    file.take(HEADER_LEN as u64).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+fn sniff(header: &[u8]) -> Option<Signature> {
+    use FileType::*;
+
+    const TIFF_FAMILY: &[&str] = &["tif", "tiff", "dng", "nef", "nrw", "arw", "pef", "srw"];
+    const ZIP_FAMILY: &[&str] = &["zip", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub"];
+    const OLE_FAMILY: &[&str] = &["doc", "xls", "ppt"];
+
+    let starts = |magic: &[u8]| header.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    let signature = if starts(&[0xFF, 0xD8, 0xFF]) {
+        Signature::new(Image, "jpeg", &["jpg", "jpeg", "jpe"])
+    } else if starts(b"\x89PNG\r\n\x1a\n") {
+        Signature::new(Image, "png", &["png"])
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        Signature::new(Image, "gif", &["gif"])
+    } else if starts(b"BM") && at(6, &[0, 0, 0, 0]) {
+        Signature::new(Image, "bmp", &["bmp"])
+    } else if starts(b"II*\0") && at(8, b"CR") {
+        Signature::new(Image, "cr2", &["cr2"])
+    } else if starts(b"II*\0") || starts(b"MM\0*") {
+        Signature::new(Image, "tiff", TIFF_FAMILY)
+    } else if starts(b"IIRO") || starts(b"IIRS") || starts(b"MMOR") {
+        Signature::new(Image, "orf", &["orf"])
+    } else if starts(b"IIU\0") {
+        Signature::new(Image, "rw2", &["rw2"])
+    } else if starts(b"FUJIFILMCCD-RAW") {
+        Signature::new(Image, "raf", &["raf"])
+    } else if starts(b"RIFF") && at(8, b"WEBP") {
+        Signature::new(Image, "webp", &["webp"])
+    } else if starts(b"RIFF") && at(8, b"WAVE") {
+        Signature::new(Audio, "wav", &["wav"])
+    } else if starts(b"RIFF") && at(8, b"AVI ") {
+        Signature::new(Video, "avi", &["avi"])
+    } else if at(4, b"ftyp") {
+        return sniff_ftyp(header.get(8..12)?);
+    } else if at(4, b"moov") || at(4, b"mdat") || at(4, b"wide") {
+        // Pre-ftyp QuickTime files start straight with an atom
+        Signature::new(Video, "mov", &["mov", "qt", "mp4"])
+    } else if starts(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        Signature::new(Video, "matroska", &["mkv", "webm"])
+    } else if starts(b"FLV\x01") {
+        Signature::new(Video, "flv", &["flv"])
+    } else if starts(&[0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11]) {
+        Signature::new(Video, "asf", &["wmv", "asf", "wma"])
+    } else if starts(b"%PDF-") {
+        Signature::new(Document, "pdf", &["pdf"])
+    } else if starts(b"{\\rtf") {
+        Signature::new(Document, "rtf", &["rtf"])
+    } else if starts(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]) {
+        Signature::new(Document, "ole", OLE_FAMILY)
+    } else if starts(b"fLaC") {
+        Signature::new(Audio, "flac", &["flac"])
+    } else if starts(b"OggS") {
+        Signature::new(Audio, "ogg", &["ogg", "oga", "opus"])
+    } else if starts(b"ID3") || (header.len() >= 2 && header[0] == 0xFF && matches!(header[1], 0xFB | 0xF3 | 0xF2)) {
+        Signature::new(Audio, "mp3", &["mp3"])
+    } else if header.len() >= 2 && header[0] == 0xFF && matches!(header[1], 0xF1 | 0xF9) {
+        Signature::new(Audio, "aac", &["aac"])
+    } else if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") {
+        Signature::new(Archive, "zip", ZIP_FAMILY)
+    } else if starts(&[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C]) {
+        Signature::new(Archive, "7z", &["7z"])
+    } else if starts(b"Rar!\x1A\x07") {
+        Signature::new(Archive, "rar", &["rar"])
+    } else if starts(&[0x1F, 0x8B]) {
+        Signature::new(Archive, "gzip", &["gz", "tgz"])
+    } else if at(257, b"ustar") {
+        Signature::new(Archive, "tar", &["tar"])
+    } else {
+        return None;
+    };
+
+    Some(signature)
+}
+
+/// ISO base media files (MP4, MOV, HEIC, AVIF, 3GP, M4A) are told apart by their major brand
+fn sniff_ftyp(brand: &[u8]) -> Option<Signature> {
+    use FileType::*;
+
+    let signature = match brand {
+        b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"mif1" | b"msf1" => {
+            Signature::new(Image, "heif", &["heic", "heif", "hif"])
+        }
+        b"avif" | b"avis" => Signature::new(Image, "avif", &["avif"]),
+        b"qt  " => Signature::new(Video, "mov", &["mov", "qt"]),
+        _ if brand.starts_with(b"3gp") || brand.starts_with(b"3g2") => Signature::new(Video, "3gp", &["3gp", "3g2"]),
+        b"M4A " | b"M4B " => Signature::new(Audio, "m4a", &["m4a", "m4b"]),
+        b"M4V " | b"M4VH" | b"M4VP" => Signature::new(Video, "m4v", &["m4v", "mp4"]),
+        b"crx " => Signature::new(Image, "cr3", &["cr3"]),
+        // Generic brands say nothing about the content, so any ISO media extension is honest
+        b"isom" | b"iso2" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42" | b"avc1" | b"dash" => {
+            Signature::new(Video, "mp4", &["mp4", "m4v", "m4a", "m4b", "mov", "3gp", "3g2"])
+        }
+        _ => Signature::new(Video, "mp4", &["mp4", "m4v"]),
+    };
+
+    Some(signature)
+}
+
+fn file_type_from_extension(ext: &str) -> Option<FileType> {
+    if is_image_extension(ext) {
+        Some(FileType::Image)
+    } else if is_video_extension(ext) {
+        Some(FileType::Video)
+    } else if is_document_extension(ext) {
+        Some(FileType::Document)
+    } else if is_audio_extension(ext) {
+        Some(FileType::Audio)
+    } else if is_archive_extension(ext) {
+        Some(FileType::Archive)
+    } else {
+        None
+    }
+}
+
+fn is_image_extension(ext: &str) -> bool {
+    matches!(
+        ext,
+        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "heic" | "heif" | "tiff" | "tif"
+    ) || is_raw_extension(ext)
+}
+
+/// Camera RAW formats. Most are TIFF underneath, so this goes by extension.
+pub fn is_raw_extension(ext: &str) -> bool {
+    matches!(
+        ext,
+        "cr2" | "cr3" | "nef" | "nrw" | "arw" | "dng" | "raf" | "orf" | "rw2" | "pef" | "srw"
+    )
+}
+
+/// Photos and videos, the files worth unpacking from an archive
+pub fn is_media_extension(ext: &str) -> bool {
+    is_image_extension(ext) || is_video_extension(ext)
+}
+
+fn is_video_extension(ext: &str) -> bool {
+    matches!(
+        ext,
+        "mp4" | "mov" | "avi" | "mkv" | "m4v" | "wmv" | "flv" | "webm" | "3gp"
+    )
+}
+
+fn is_document_extension(ext: &str) -> bool {
+    matches!(
+        ext,
+        "pdf" | "doc" | "docx" | "txt" | "odt" | "rtf"
+    )
+}
+
+fn is_audio_extension(ext: &str) -> bool {
+    matches!(
+        ext,
+        "mp3" | "flac" | "m4a" | "m4b" | "wav" | "ogg" | "aac"
+    )
+}
+
+fn is_archive_extension(ext: &str) -> bool {
+    matches!(
+        ext,
+        "zip" | "7z" | "rar" | "tar" | "tgz"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn detect(dir: &TempDir, name: &str, contents: &[u8]) -> Option<Detection> {
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        detect_file_type(&path)
+    }
+
+    #[test]
+    fn test_detect_by_content() {
+        let dir = TempDir::new().unwrap();
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F', b'I', b'F'];
+
+        // Honest extension
+        let d = detect(&dir, "a.jpg", &jpeg).unwrap();
+        assert!(matches!(d.file_type, FileType::Image));
+        assert!(!d.extension_mismatch);
+
+        // JPEG saved as .png by a messaging app
+        let d = detect(&dir, "b.png", &jpeg).unwrap();
+        assert_eq!(d.format, Some("jpeg"));
+        assert!(d.extension_mismatch);
+
+        // No extension at all
+        let d = detect(&dir, "c", &jpeg).unwrap();
+        assert!(matches!(d.file_type, FileType::Image));
+        assert!(!d.extension_mismatch);
+
+        // QuickTime movie renamed to .mp4
+        let d = detect(&dir, "d.mp4", b"\0\0\0\x14ftypqt  \0\0\0\0").unwrap();
+        assert!(matches!(d.file_type, FileType::Video));
+        assert!(d.extension_mismatch);
+
+        // Generic brand: the extension decides, and isn't a mismatch
+        let d = detect(&dir, "voice.m4a", b"\0\0\0\x18ftypmp42\0\0\0\0").unwrap();
+        assert!(matches!(d.file_type, FileType::Audio));
+        assert!(!d.extension_mismatch);
+        let d = detect(&dir, "clip.3gp", b"\0\0\0\x18ftypisom\0\0\0\0").unwrap();
+        assert!(matches!(d.file_type, FileType::Video));
+        assert!(!d.extension_mismatch);
+
+        // DOCX is a ZIP container but stays a document
+        let d = detect(&dir, "e.docx", b"PK\x03\x04rest").unwrap();
+        assert!(matches!(d.file_type, FileType::Document));
+        assert!(!d.extension_mismatch);
+
+        // RAW formats, TIFF-based or not
+        let d = detect(&dir, "f.CR2", b"II*\0\x10\0\0\0CR\x02\0").unwrap();
+        assert_eq!(d.format, Some("cr2"));
+        assert!(!d.extension_mismatch);
+        let d = detect(&dir, "g.nef", b"MM\0*\0\0\0\x08").unwrap();
+        assert!(matches!(d.file_type, FileType::Image));
+        assert!(!d.extension_mismatch);
+        let d = detect(&dir, "h.raf", b"FUJIFILMCCD-RAW 0201").unwrap();
+        assert_eq!(d.format, Some("raf"));
+    }
+
+    #[test]
+    fn test_extension_fallback() {
+        let dir = TempDir::new().unwrap();
+
+        let d = detect(&dir, "notes.txt", b"just some text").unwrap();
+        assert!(matches!(d.file_type, FileType::Document));
+        assert_eq!(d.format, None);
+
+        assert!(detect(&dir, "unknown.xyz", b"just some text").is_none());
+    }
+}
@@ -0,0 +1,152 @@
+use std::path::{Component, Path};
+use serde::{Deserialize, Serialize};
+use crate::types::*;
+
+/// User-configurable rules for spotting memes, saved images and messaging-app
+/// downloads. Every rule that matches a file is recorded on it by name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadRules {
+    pub filename_patterns: Vec<String>,     // Case-insensitive substrings of the file name
+    pub folders: Vec<String>,               // Folder names anywhere in the path, case-insensitive
+    pub social_dimensions: Vec<(u32, u32)>, // Sizes social networks re-encode to (either orientation)
+    pub missing_camera_exif: bool,          // Image has no camera make or model
+    pub stripped_metadata: bool,            // Image has no EXIF at all that we can see
+    pub min_matches: usize,                 // Rules that must fire before a file is flagged
+}
+
+impl Default for DownloadRules {
+    fn default() -> Self {
+        let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect();
+        Self {
+            filename_patterns: strings(&[
+                "meme", "funny", "download", "untitled", "-wa0", "received_", "fb_img_",
+                "tumblr_", "pinterest", "giphy", "snapchat-", "telegram", "reddit",
+            ]),
+            folders: strings(&[
+                "WhatsApp Images", "WhatsApp Animated Gifs", "WhatsApp Video", "Telegram Images",
+                "Telegram Video", "Messenger", "Signal", "Instagram", "Snapchat", "Facebook",
+                "Twitter", "Reddit", "Download", "Downloads", "Saved Pictures",
+            ]),
+            social_dimensions: vec![
+                (1080, 1080), (1080, 1350), (1080, 566), (1080, 608), // Instagram
+                (1200, 630), (1200, 675), (1600, 900), (1200, 1200),  // Facebook, X
+                (1600, 1200), (1280, 960), (1280, 720), (800, 600),   // WhatsApp, Telegram
+                (640, 640), (720, 720), (500, 500),
+            ],
+            missing_camera_exif: true,
+            stripped_metadata: true,
+            min_matches: 2,
+        }
+    }
+}
+
+impl DownloadRules {
+    /// Names of every rule that fires for a file, e.g. "folder:WhatsApp Images"
+    pub fn matches(&self, file: &FileMetadata) -> Vec<String> {
+        if !matches!(file.file_type, FileType::Image | FileType::Video) {
+            return Vec::new();
+        }
+
+        let mut fired = Vec::new();
+
+        let name = file.file_name.to_lowercase();
+        fired.extend(self.filename_patterns.iter()
+            .filter(|p| !p.is_empty() && name.contains(&p.to_lowercase()))
+            .map(|p| format!("filename:{p}")));
+
+        let folders: Vec<String> = Path::new(&file.path)
+            .parent()
+            .into_iter()
+            .flat_map(|parent| parent.components())
+            .filter_map(|c| match c {
+                Component::Normal(part) => Some(part.to_string_lossy().to_lowercase()),
+                _ => None,
+            })
+            .collect();
+        fired.extend(self.folders.iter()
+            .filter(|f| folders.contains(&f.to_lowercase()))
+            .map(|f| format!("folder:{f}")));
+
+        if let (Some(w), Some(h)) = (file.width, file.height) {
+            if self.social_dimensions.iter().any(|&d| d == (w, h) || d == (h, w)) {
+                fired.push(format!("social-size:{w}x{h}"));
+            }
+        }
+
+        // EXIF rules only make sense for still images. Stripped metadata implies
+        // no camera, so only the stronger of the two counts towards min_matches.
+        if matches!(file.file_type, FileType::Image) {
+            let has_camera = file.camera_make.is_some() || file.camera_model.is_some();
+            let has_any_exif = has_camera
+                || file.date_source == Some(DateSource::Exif)
+                || file.orientation.is_some()
+                || file.latitude.is_some()
+                || file.iso.is_some();
+
+            if self.stripped_metadata && !has_any_exif {
+                fired.push("stripped-metadata".to_string());
+            } else if self.missing_camera_exif && !has_camera {
+                fired.push("no-camera-exif".to_string());
+            }
+        }
+
+        fired
+    }
+
+    /// Tag a file with the rules that fired and whether enough did to flag it
+    pub fn classify(&self, file: &mut FileMetadata) {
+        file.download_rules = self.matches(file);
+        file.is_likely_download = file.download_rules.len() >= self.min_matches.max(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_rules() {
+        let rules = DownloadRules::default();
+
+        // Camera originals are no longer flagged just for being called IMG_
+        let mut camera = FileMetadata::for_test("/p/DCIM/IMG_1234.JPG", "a", 10, FileType::Image);
+        camera.camera_make = Some("Apple".into());
+        camera.date_source = Some(DateSource::Exif);
+        rules.classify(&mut camera);
+        assert!(camera.download_rules.is_empty());
+        assert!(!camera.is_likely_download);
+
+        let mut whatsapp = FileMetadata::for_test("/p/WhatsApp Images/IMG-20230714-WA0001.jpg", "b", 10, FileType::Image);
+        whatsapp.width = Some(1600);
+        whatsapp.height = Some(1200);
+        rules.classify(&mut whatsapp);
+        assert_eq!(whatsapp.download_rules, vec![
+            "filename:-wa0", "folder:WhatsApp Images", "social-size:1600x1200", "stripped-metadata",
+        ]);
+        assert!(whatsapp.is_likely_download);
+
+        // One rule alone isn't enough; users can lower the bar
+        let mut edited = FileMetadata::for_test("/p/edits/holiday.png", "c", 10, FileType::Image);
+        edited.orientation = Some(1);
+        rules.classify(&mut edited);
+        assert_eq!(edited.download_rules, vec!["no-camera-exif"]);
+        assert!(!edited.is_likely_download);
+
+        let lenient = DownloadRules { min_matches: 1, ..DownloadRules::default() };
+        lenient.classify(&mut edited);
+        assert!(edited.is_likely_download);
+    }
+
+    #[test]
+    fn test_dates_from_the_name_are_not_exif() {
+        let path = std::path::Path::new("/p/IMG-20190703-WA0001.jpg");
+        let resolved = crate::dates::resolve_date(path, None, &Default::default()).unwrap();
+        let mut whatsapp = FileMetadata::for_test(&path.to_string_lossy(), "a", 10, FileType::Image);
+        (whatsapp.date_taken, whatsapp.date_taken_offset, whatsapp.date_source) =
+            (Some(resolved.date), resolved.offset, Some(resolved.source));
+
+        DownloadRules::default().classify(&mut whatsapp);
+        assert_eq!(whatsapp.download_rules, vec!["filename:-wa0", "stripped-metadata"]);
+    }
+}
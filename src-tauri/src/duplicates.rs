@@ -0,0 +1,145 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::similarity::{group_similar, UnionFind};
+use crate::types::*;
+
+/// Find groups of identical (same SHA-256) or visually near-identical files,
+/// pick a keeper for each and mark every other member as a duplicate.
+/// `max_distance` of None skips perceptual matching and only groups exact copies.
+pub fn find_duplicate_groups(
+    files: &mut [FileMetadata],
+    max_distance: Option<u32>,
+    rule: &KeeperRule,
+) -> Vec<DuplicateGroup> {
+    let index_of: HashMap<String, usize> = files.iter()
+        .enumerate()
+        .map(|(i, f)| (f.path.clone(), i))
+        .collect();
+
+    // Exact copies share a hash; similar images are merged on top
+    let mut sets = UnionFind::new(files.len());
+    let mut first_with_hash: HashMap<&str, usize> = HashMap::new();
+    for (i, file) in files.iter().enumerate() {
+        if let Some(hash) = &file.hash {
+            sets.union(i, *first_with_hash.entry(hash.as_str()).or_insert(i));
+        }
+    }
+
+    if let Some(max_distance) = max_distance {
+        for similar in group_similar(files, max_distance) {
+            let mut members = similar.files.iter().map(|f| index_of[&f.path]);
+            if let Some(first) = members.next() {
+                members.for_each(|i| sets.union(first, i));
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..files.len() {
+        members.entry(sets.find(i)).or_default().push(i);
+    }
+
+    for file in files.iter_mut() {
+        file.is_duplicate = false;
+    }
+
+    let mut groups: Vec<DuplicateGroup> = members.into_values()
+        .filter(|m| m.len() > 1)
+        .map(|mut m| {
+            m.sort_by(|&a, &b| compare_keepers(&files[a], &files[b], rule));
+            let keeper = files[m[0]].path.clone();
+            let kind = if files[m[0]].hash.is_some() && m.iter().all(|&i| files[i].hash == files[m[0]].hash) {
+                DuplicateKind::Exact
+            } else {
+                DuplicateKind::Similar
+            };
+
+            for &i in &m[1..] {
+                files[i].is_duplicate = true;
+            }
+
+            DuplicateGroup {
+                kind,
+                keeper,
+                files: m.iter().map(|&i| files[i].clone()).collect(),
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| a.keeper.cmp(&b.keeper));
+    groups
+}
+
+/// Order candidates best-first: the chosen rule decides, the others break ties
+fn compare_keepers(a: &FileMetadata, b: &FileMetadata, rule: &KeeperRule) -> Ordering {
+    let by_resolution = || pixels(b).cmp(&pixels(a));
+    let by_date = || match (a.date_taken, b.date_taken) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    let by_exif = || has_camera_exif(b).cmp(&has_camera_exif(a));
+
+    let primary = match rule {
+        KeeperRule::HighestResolution => by_resolution(),
+        KeeperRule::EarliestDate => by_date(),
+        KeeperRule::HasExif => by_exif(),
+        KeeperRule::PreferredFolder(folder) => {
+            let in_folder = |f: &FileMetadata| Path::new(&f.path).starts_with(folder);
+            in_folder(b).cmp(&in_folder(a))
+        }
+    };
+
+    primary
+        .then_with(by_exif)
+        .then_with(by_resolution)
+        .then_with(by_date)
+        .then_with(|| a.path.cmp(&b.path))
+}
+
+fn pixels(file: &FileMetadata) -> u64 {
+    match (file.width, file.height) {
+        (Some(w), Some(h)) => w as u64 * h as u64,
+        _ => 0,
+    }
+}
+
+fn has_camera_exif(file: &FileMetadata) -> bool {
+    file.camera_make.is_some() || file.camera_model.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeper_rules() {
+        let mut small = FileMetadata::for_test("/p/WhatsApp/a.jpg", "x", 10, FileType::Image);
+        small.width = Some(800);
+        small.height = Some(600);
+        small.date_taken = Some("2019-01-01T00:00:00Z".parse().unwrap());
+
+        let mut original = FileMetadata::for_test("/p/Camera/a.jpg", "x", 10, FileType::Image);
+        original.width = Some(4000);
+        original.height = Some(3000);
+        original.date_taken = Some("2019-01-02T00:00:00Z".parse().unwrap());
+
+        let unrelated = FileMetadata::for_test("/p/Camera/b.jpg", "y", 10, FileType::Image);
+        let mut files = vec![small, original, unrelated];
+
+        let groups = find_duplicate_groups(&mut files, None, &KeeperRule::HighestResolution);
+        assert_eq!(groups.len(), 1);
+        assert!(matches!(groups[0].kind, DuplicateKind::Exact));
+        assert_eq!(groups[0].keeper, "/p/Camera/a.jpg");
+        assert!(files[0].is_duplicate && !files[1].is_duplicate && !files[2].is_duplicate);
+
+        let groups = find_duplicate_groups(&mut files, None, &KeeperRule::EarliestDate);
+        assert_eq!(groups[0].keeper, "/p/WhatsApp/a.jpg");
+        assert!(!files[0].is_duplicate && files[1].is_duplicate);
+
+        let groups = find_duplicate_groups(&mut files, None, &KeeperRule::PreferredFolder("/p/Camera".into()));
+        assert_eq!(groups[0].keeper, "/p/Camera/a.jpg");
+    }
+}
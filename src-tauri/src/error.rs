@@ -0,0 +1,95 @@
+use std::io;
+use std::sync::PoisonError;
+use serde::{Serialize, Serializer};
+use std::path::Path;
+use crate::types::{ScanError, ScanErrorKind, ScanStage};
+
+/// Backend error type. Serialized as its message, so Tauri commands can return it
+/// directly and the frontend keeps receiving a plain string.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Catalog error: {0}")]
+    Catalog(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error("EXIF error: {0}")]
+    Exif(#[from] exif::Error),
+    #[error(transparent)]
+    Walk(#[from] walkdir::Error),
+    #[error("Trash error: {0}")]
+    Trash(#[from] trash::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Archive error: {0}")]
+    Archive(#[from] zip::result::ZipError),
+    #[error("Invalid scan pattern: {0}")]
+    Pattern(#[from] ignore::Error),
+    #[error("The catalog is unavailable after an earlier failure; restart the app")]
+    LockPoisoned,
+    #[error("No active scan with session id {0}")]
+    SessionNotFound(String),
+    #[error("No resolution with id {0}")]
+    ResolutionNotFound(String),
+    #[error("No files to organize")]
+    NothingToOrganize,
+    #[error("Destination path does not exist: {0}")]
+    DestinationMissing(String),
+    #[error("{0}")]
+    Invalid(String),    // A request that can't be carried out as asked
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl<T> From<PoisonError<T>> for Error {
+    fn from(_: PoisonError<T>) -> Self {
+        Error::LockPoisoned
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Error {
+    /// Coarse category for per-file scan error reports
+    pub fn kind(&self) -> ScanErrorKind {
+        match self {
+            Error::Io(e) => io_kind(e),
+            Error::Walk(e) => e.io_error().map(io_kind).unwrap_or(ScanErrorKind::Other),
+            Error::Image(image::ImageError::IoError(e)) => io_kind(e),
+            Error::Image(image::ImageError::Decoding(_)) => ScanErrorKind::Corrupt,
+            Error::Image(image::ImageError::Unsupported(_)) => ScanErrorKind::Unsupported,
+            Error::Exif(exif::Error::Io(e)) => io_kind(e),
+            Error::Exif(exif::Error::NotSupported(_)) => ScanErrorKind::Unsupported,
+            Error::Exif(_) => ScanErrorKind::Corrupt,
+            Error::Archive(zip::result::ZipError::Io(e)) => io_kind(e),
+            Error::Archive(zip::result::ZipError::UnsupportedArchive(_)) => ScanErrorKind::Unsupported,
+            Error::Archive(_) => ScanErrorKind::Corrupt,
+            _ => ScanErrorKind::Other,
+        }
+    }
+}
+
+fn io_kind(error: &io::Error) -> ScanErrorKind {
+    match error.kind() {
+        io::ErrorKind::NotFound => ScanErrorKind::NotFound,
+        io::ErrorKind::PermissionDenied => ScanErrorKind::PermissionDenied,
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ScanErrorKind::Corrupt,
+        _ => ScanErrorKind::Io,
+    }
+}
+
+impl ScanError {
+    pub fn new(path: impl AsRef<Path>, stage: ScanStage, error: &Error) -> Self {
+        Self {
+            path: path.as_ref().to_string_lossy().to_string(),
+            stage,
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
@@ -1,7 +1,12 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 use chrono::Utc;
+use crate::companions::companion_name;
+use crate::archives::{entry_path, extract_entries, split_entry_path};
+use crate::error::{Error, Result};
 use crate::types::*;
 
 /// Handle duplicate filenames by appending a counter
@@ -30,11 +35,78 @@ fn resolve_duplicate_filename(destination: &Path) -> PathBuf {
     }
 }
 
+/// The file another one follows: a RAW's JPEG, or a Live Photo clip's still
+fn lead_of(file: &FileMetadata) -> Option<&String> {
+    file.companion_of.as_ref()
+        .or(file.paired_with.as_ref().filter(|_| matches!(file.file_type, FileType::Video)))
+}
+
+/// Copy, or move by copying then deleting the source
+fn transfer(source: &Path, destination: &Path, mode: &OperationMode) -> io::Result<u64> {
+    let bytes = fs::copy(source, destination)?;
+    if matches!(mode, OperationMode::Move) {
+        if let Err(e) = fs::remove_file(source) {
+            // Copy succeeded but delete failed - not ideal but file is safe
+            eprintln!("Warning: Failed to delete source file after copy: {e}");
+        }
+    }
+    Ok(bytes)
+}
+
+/// Destination for the other half of a pair: the lead's final name with the follower's extension
+fn paired_path(still: &Path, planned: &Path) -> PathBuf {
+    let stem = still.file_stem().unwrap_or_default().to_string_lossy();
+    match planned.extension() {
+        Some(extension) => still.with_file_name(format!("{stem}.{}", extension.to_string_lossy())),
+        None => still.with_file_name(stem.as_ref()),
+    }
+}
+
+/// Pull the planned archive entries into `staging`, one pass per archive. Maps
+/// each entry's virtual path to its staged copy, or to why it couldn't be had.
+fn extract_planned<'a>(
+    files: impl IntoIterator<Item = &'a FileMetadata>,
+    staging: &Path,
+) -> HashMap<String, Result<PathBuf>> {
+    let mut by_archive: HashMap<&Path, HashMap<String, PathBuf>> = HashMap::new();
+    for (i, (archive, name)) in files.into_iter().filter_map(|f| split_entry_path(&f.path)).enumerate() {
+        by_archive.entry(archive).or_default().insert(name.to_string(), staging.join(i.to_string()));
+    }
+
+    let mut extracted = HashMap::new();
+    for (archive, destinations) in by_archive {
+        let written = extract_entries(archive, &destinations);
+        for (name, staged) in destinations {
+            let outcome = match &written {
+                Ok(written) if written.contains(&name) => Ok(staged),
+                Ok(_) => Err(io::Error::new(io::ErrorKind::NotFound, format!("{name} is no longer in {}", archive.display())).into()),
+                // Shared by every entry of the archive
+                Err(e) => Err(Error::Invalid(format!("Could not read {}: {e}", archive.display()))),
+            };
+            extracted.insert(entry_path(archive, &name), outcome);
+        }
+    }
+    extracted
+}
+
+/// Move a staged entry to its destination, keeping the modification time it was extracted with
+fn place_staged(staged: &Path, destination: &Path) -> io::Result<u64> {
+    if fs::rename(staged, destination).is_ok() {
+        return Ok(fs::metadata(destination)?.len());
+    }
+    // Staging may be on another filesystem
+    let modified = fs::metadata(staged)?.modified()?;
+    let bytes = fs::copy(staged, destination)?;
+    fs::File::options().write(true).open(destination)?.set_modified(modified)?;
+    fs::remove_file(staged)?;
+    Ok(bytes)
+}
+
 /// Execute organization plan - copy or move files
 pub fn execute_organization_plan(
     plan: OrganizationPlan,
     source_files: Vec<FileMetadata>,
-) -> Result<OperationResult, String> {
+) -> Result<OperationResult> {
     let start_time = Instant::now();
     let mut operations: Vec<FileOperation> = Vec::new();
     let mut successful_count = 0;
@@ -43,23 +115,38 @@ pub fn execute_organization_plan(
     let mut total_size_processed = 0u64;
 
     // Create a map of file paths to their destinations
-    let mut file_destinations: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut file_destinations: HashMap<String, String> = HashMap::new();
     
     for folder in &plan.folders {
-        for file_name in &folder.files {
-            // Find the source file
-            if let Some(source_file) = source_files.iter().find(|f| f.file_name == *file_name) {
-                let dest_path = Path::new(&folder.path).join(file_name);
-                file_destinations.insert(source_file.path.clone(), dest_path.to_string_lossy().to_string());
-            }
+        for (file_name, source) in folder.files.iter().zip(&folder.sources) {
+            let dest_path = Path::new(&folder.path).join(file_name);
+            file_destinations.insert(source.clone(), dest_path.to_string_lossy().to_string());
         }
     }
 
+    // Archive entries are extracted up front, so each archive is read only once
+    let planned_entries: Vec<&FileMetadata> = source_files.iter()
+        .filter(|f| file_destinations.contains_key(&f.path) && split_entry_path(&f.path).is_some())
+        .collect();
+    let staging = if planned_entries.is_empty() { None } else { Some(tempfile::tempdir()?) };
+    let mut extracted = match &staging {
+        Some(staging) => extract_planned(planned_entries, staging.path()),
+        None => HashMap::new(),
+    };
+
+    // Live Photo clips and RAWs go after the file they follow, so they can take
+    // the name it ended up with
+    let cataloged: HashSet<String> = source_files.iter().map(|f| f.path.clone()).collect();
+    let (followers, leads): (Vec<FileMetadata>, Vec<FileMetadata>) = source_files.into_iter()
+        .partition(|f| lead_of(f).is_some());
+    let mut placed: HashMap<String, PathBuf> = HashMap::new();
+
     // Process each file
-    for source_file in source_files {
+    for source_file in leads.into_iter().chain(followers) {
         let source_path = Path::new(&source_file.path);
-        
-        if !source_path.exists() {
+        let in_archive = split_entry_path(&source_file.path).is_some();
+
+        if !in_archive && !source_path.exists() {
             operations.push(FileOperation {
                 source_path: source_file.path.clone(),
                 destination_path: String::new(),
@@ -85,7 +172,28 @@ pub fn execute_organization_plan(
             }
         };
 
-        let dest_path = Path::new(dest_path_str);
+        // A follower lands next to its lead under the same name, or stays put if the lead didn't move
+        let lead = lead_of(&source_file).filter(|p| file_destinations.contains_key(*p));
+        let paired_dest = match lead.map(|p| placed.get(p)) {
+            Some(Some(lead)) => Some(paired_path(lead, Path::new(dest_path_str))),
+            Some(None) => {
+                let reason = if source_file.companion_of.is_some() {
+                    "The photo it was shot with was not organized"
+                } else {
+                    "Its Live Photo still was not organized"
+                };
+                operations.push(FileOperation {
+                    source_path: source_file.path.clone(),
+                    destination_path: String::new(),
+                    status: OperationStatus::Skipped,
+                    error_message: Some(reason.to_string()),
+                });
+                skipped_count += 1;
+                continue;
+            }
+            None => None,
+        };
+        let dest_path = paired_dest.as_deref().unwrap_or(Path::new(dest_path_str));
         
         // Create destination directory
         if let Some(parent) = dest_path.parent() {
@@ -104,27 +212,15 @@ pub fn execute_organization_plan(
         // Resolve duplicate filename if necessary
         let final_dest = resolve_duplicate_filename(dest_path);
 
-        // Perform operation
-        let result = match plan.mode {
-            OperationMode::Copy => fs::copy(source_path, &final_dest),
-            OperationMode::Move => {
-                // Copy first, then delete if successful
-                match fs::copy(source_path, &final_dest) {
-                    Ok(bytes) => {
-                        // Delete source after successful copy
-                        if let Err(e) = fs::remove_file(source_path) {
-                            // Copy succeeded but delete failed - not ideal but file is safe
-                            eprintln!("Warning: Failed to delete source file after copy: {e}");
-                        }
-                        Ok(bytes)
-                    }
-                    Err(e) => Err(e),
-                }
-            }
+        // Perform operation. Archive entries come from staging; the archive itself
+        // is left as it was, even when moving.
+        let result = match extracted.remove(&source_file.path) {
+            Some(staged) => staged.and_then(|staged| Ok(place_staged(&staged, &final_dest)?)),
+            None => transfer(source_path, &final_dest, &plan.mode).map_err(Error::from),
         };
-
         match result {
             Ok(_) => {
+                placed.insert(source_file.path.clone(), final_dest.clone());
                 operations.push(FileOperation {
                     source_path: source_file.path.clone(),
                     destination_path: final_dest.to_string_lossy().to_string(),
@@ -133,6 +229,30 @@ pub fn execute_organization_plan(
                 });
                 successful_count += 1;
                 total_size_processed += source_file.file_size;
+
+                // Sidecars aren't in the plan; they go wherever their photo went
+                let sidecars = source_file.companions.iter()
+                    .map(Path::new)
+                    .filter(|c| !cataloged.contains(&*c.to_string_lossy()) && c.exists());
+                for sidecar in sidecars {
+                    let dest = resolve_duplicate_filename(
+                        &final_dest.with_file_name(companion_name(sidecar, source_path, &final_dest)),
+                    );
+                    let result = transfer(sidecar, &dest, &plan.mode);
+                    operations.push(FileOperation {
+                        source_path: sidecar.to_string_lossy().to_string(),
+                        destination_path: dest.to_string_lossy().to_string(),
+                        status: if result.is_ok() { OperationStatus::Success } else { OperationStatus::Failed },
+                        error_message: result.as_ref().err().map(|e| e.to_string()),
+                    });
+                    match result {
+                        Ok(bytes) => {
+                            successful_count += 1;
+                            total_size_processed += bytes;
+                        }
+                        Err(_) => failed_count += 1,
+                    }
+                }
             }
             Err(e) => {
                 operations.push(FileOperation {
@@ -179,4 +299,160 @@ mod tests {
         let resolved = resolve_duplicate_filename(&file_path);
         assert_eq!(resolved.file_name().unwrap().to_str().unwrap(), "test_1.txt");
     }
+
+    #[test]
+    fn test_live_photo_halves_move_together() {
+        let temp_dir = TempDir::new().unwrap();
+        let (source, dest) = (temp_dir.path().join("src"), temp_dir.path().join("2019"));
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        File::create(dest.join("IMG_0001.HEIC")).unwrap(); // Forces the still to be renamed
+
+        let file = |name: &str, file_type: FileType, partner: &str| {
+            let path = source.join(name);
+            File::create(&path).unwrap();
+            let mut file = FileMetadata::for_test(&path.to_string_lossy(), "", 0, file_type);
+            file.paired_with = Some(source.join(partner).to_string_lossy().to_string());
+            file
+        };
+        // Clip listed first, and planned for a different folder
+        let files = vec![
+            file("IMG_0001.MOV", FileType::Video, "IMG_0001.HEIC"),
+            file("IMG_0001.HEIC", FileType::Image, "IMG_0001.MOV"),
+        ];
+        let folder = |path: &Path, name: &str| FolderPreview {
+            path: path.to_string_lossy().to_string(),
+            file_count: 1,
+            total_size: 0,
+            files: vec![name.to_string()],
+            sources: vec![source.join(name).to_string_lossy().to_string()],
+        };
+        let plan = OrganizationPlan {
+            source_root: source.to_string_lossy().to_string(),
+            destination_root: temp_dir.path().to_string_lossy().to_string(),
+            strategy: OrganizationStrategy::FileType,
+            mode: OperationMode::Move,
+            folders: vec![folder(&temp_dir.path().join("Videos"), "IMG_0001.MOV"), folder(&dest, "IMG_0001.HEIC")],
+            total_files: 2,
+            total_size: 0,
+            files_without_dates: 0,
+        };
+
+        let result = execute_organization_plan(plan, files).unwrap();
+        assert_eq!(result.successful_count, 2);
+        assert!(dest.join("IMG_0001_1.HEIC").exists());
+        assert!(dest.join("IMG_0001_1.MOV").exists());
+        assert!(!source.join("IMG_0001.MOV").exists());
+    }
+
+    #[test]
+    fn test_raw_and_sidecars_follow_their_jpeg() {
+        let temp_dir = TempDir::new().unwrap();
+        let (source, dest) = (temp_dir.path().join("src"), temp_dir.path().join("2019"));
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        File::create(dest.join("IMG_1.JPG")).unwrap();
+        for name in ["IMG_1.JPG", "IMG_1.CR2", "IMG_1.CR2.xmp", "IMG_1.dop"] {
+            File::create(source.join(name)).unwrap();
+        }
+
+        let path = |name: &str| source.join(name).to_string_lossy().to_string();
+        let mut jpeg = FileMetadata::for_test(&path("IMG_1.JPG"), "", 0, FileType::Image);
+        jpeg.companions = vec![path("IMG_1.CR2"), path("IMG_1.CR2.xmp"), path("IMG_1.dop")];
+        let mut raw = FileMetadata::for_test(&path("IMG_1.CR2"), "", 0, FileType::Image);
+        raw.companion_of = Some(path("IMG_1.JPG"));
+
+        let plan = OrganizationPlan {
+            source_root: source.to_string_lossy().to_string(),
+            destination_root: temp_dir.path().to_string_lossy().to_string(),
+            strategy: OrganizationStrategy::Year,
+            mode: OperationMode::Move,
+            folders: vec![FolderPreview {
+                path: dest.to_string_lossy().to_string(),
+                file_count: 2,
+                total_size: 0,
+                files: vec!["IMG_1.CR2".to_string(), "IMG_1.JPG".to_string()],
+                sources: vec![path("IMG_1.CR2"), path("IMG_1.JPG")],
+            }],
+            total_files: 2,
+            total_size: 0,
+            files_without_dates: 0,
+        };
+
+        let result = execute_organization_plan(plan, vec![raw, jpeg]).unwrap();
+        assert_eq!(result.successful_count, 4);
+        for name in ["IMG_1_1.JPG", "IMG_1_1.CR2", "IMG_1_1.CR2.xmp", "IMG_1_1.dop"] {
+            assert!(dest.join(name).exists(), "{name}");
+        }
+        assert_eq!(fs::read_dir(&source).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_archive_entries_are_extracted() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("2019");
+        let archive = temp_dir.path().join("backup.zip");
+        crate::archives::test_support::write_zip(&archive, &[("DCIM/IMG_1.jpg", b"one"), ("DCIM/IMG_2.jpg", b"two")]);
+
+        let files: Vec<FileMetadata> = ["DCIM/IMG_1.jpg", "DCIM/IMG_2.jpg", "DCIM/IMG_3.jpg"].iter()
+            .map(|name| FileMetadata::for_test(&entry_path(&archive, name), "", 3, FileType::Image))
+            .collect();
+        let plan = OrganizationPlan {
+            source_root: temp_dir.path().to_string_lossy().to_string(),
+            destination_root: temp_dir.path().to_string_lossy().to_string(),
+            strategy: OrganizationStrategy::Year,
+            mode: OperationMode::Move,
+            folders: vec![FolderPreview {
+                path: dest.to_string_lossy().to_string(),
+                file_count: 3,
+                total_size: 9,
+                files: vec!["IMG_1.jpg".to_string(), "IMG_2.jpg".to_string(), "IMG_3.jpg".to_string()],
+                sources: files.iter().map(|f| f.path.clone()).collect(),
+            }],
+            total_files: 3,
+            total_size: 9,
+            files_without_dates: 0,
+        };
+
+        let result = execute_organization_plan(plan, files).unwrap();
+        assert_eq!((result.successful_count, result.failed_count), (2, 1));
+        assert_eq!(fs::read(dest.join("IMG_1.jpg")).unwrap(), b"one");
+        assert_eq!(fs::read(dest.join("IMG_2.jpg")).unwrap(), b"two");
+        assert!(archive.exists());
+    }
+
+    #[test]
+    fn test_same_names_from_different_folders() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("2019");
+        let files: Vec<FileMetadata> = ["a", "b"].iter()
+            .map(|folder| {
+                let path = temp_dir.path().join(folder).join("IMG_1.jpg");
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, folder).unwrap();
+                FileMetadata::for_test(&path.to_string_lossy(), "", 1, FileType::Image)
+            })
+            .collect();
+        let plan = OrganizationPlan {
+            source_root: temp_dir.path().to_string_lossy().to_string(),
+            destination_root: temp_dir.path().to_string_lossy().to_string(),
+            strategy: OrganizationStrategy::Year,
+            mode: OperationMode::Copy,
+            folders: vec![FolderPreview {
+                path: dest.to_string_lossy().to_string(),
+                file_count: 2,
+                total_size: 2,
+                files: vec!["IMG_1.jpg".to_string(), "IMG_1.jpg".to_string()],
+                sources: files.iter().map(|f| f.path.clone()).collect(),
+            }],
+            total_files: 2,
+            total_size: 2,
+            files_without_dates: 0,
+        };
+
+        let result = execute_organization_plan(plan, files).unwrap();
+        assert_eq!(result.successful_count, 2);
+        assert_eq!(fs::read(dest.join("IMG_1.jpg")).unwrap(), b"a");
+        assert_eq!(fs::read(dest.join("IMG_1_1.jpg")).unwrap(), b"b");
+    }
 }
@@ -0,0 +1,472 @@
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use exif::{Exif, In, Reader, Tag, Value};
+use crate::detect::detect_file_type;
+use crate::error::{Error, Result};
+use crate::isobmff::read_image_info;
+use crate::raw;
+use crate::types::*;
+
+/// Parsed EXIF block of a single file
+pub struct ExifData {
+    exif: Exif,
+}
+
+impl ExifData {
+    /// Read EXIF, choosing the reader by the detected container: kamadak-exif for
+    /// JPEG, TIFF (and CR2), PNG and WebP, `isobmff` for HEIF and AVIF, `raw` for
+    /// the other RAW formats. Ok(None) when the file simply has no EXIF; Err when
+    /// it has some but it is unreadable.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let exif = match detect_file_type(path).and_then(|d| d.format) {
+            Some("jpeg" | "tiff" | "cr2" | "png" | "webp") => {
+                let file = fs::File::open(path)?;
+                match Reader::new().read_from_container(&mut std::io::BufReader::new(&file)) {
+                    Ok(exif) => Some(exif),
+                    Err(exif::Error::NotFound(_)) => None,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Some("heif" | "avif") => read_image_info(path)
+                .and_then(|info| info.exif)
+                .map(|tiff| Reader::new().read_raw(tiff))
+                .transpose()?,
+            Some("orf" | "rw2" | "raf" | "cr3") => raw::read_exif(path)?,
+            // GIF, BMP, video and friends don't carry EXIF
+            _ => None,
+        };
+        Ok(exif.map(|exif| Self { exif }))
+    }
+
+    /// When the photo was taken, in the local time of the place it was taken.
+    /// The offset comes from OffsetTime* tags, then the GPS clock, then the home timezone.
+    pub fn date_taken(&self, home: &HomeTimezone) -> Option<DateTime<FixedOffset>> {
+        // Try DateTimeOriginal first (when photo was taken),
+        // then fall back to DateTime (when file was created)
+        let (local, offset) = match self.ascii(Tag::DateTimeOriginal).and_then(|s| parse_exif_datetime(&s)) {
+            Some(local) => (local, self.ascii(Tag::OffsetTimeOriginal)),
+            None => (
+                self.ascii(Tag::DateTime).and_then(|s| parse_exif_datetime(&s))?,
+                self.ascii(Tag::OffsetTime),
+            ),
+        };
+
+        let offset = offset.as_deref()
+            .and_then(parse_exif_offset)
+            .or_else(|| self.gps_offset(local));
+
+        match offset {
+            Some(offset) => offset.from_local_datetime(&local).single(),
+            None => Some(home.localize(local)),
+        }
+    }
+
+    /// Infer the UTC offset by comparing the camera clock with the GPS (UTC) clock
+    fn gps_offset(&self, local: NaiveDateTime) -> Option<FixedOffset> {
+        let date = NaiveDate::parse_from_str(&self.ascii(Tag::GPSDateStamp)?, "%Y:%m:%d").ok()?;
+        let time = match &self.exif.get_field(Tag::GPSTimeStamp, In::PRIMARY)?.value {
+            Value::Rational(values) if values.len() >= 3 && values.iter().all(|r| r.denom != 0) => {
+                let seconds = values[0].to_f64() * 3600.0 + values[1].to_f64() * 60.0 + values[2].to_f64();
+                NaiveTime::from_num_seconds_from_midnight_opt(seconds as u32, 0)?
+            }
+            _ => return None,
+        };
+        let utc = date.and_time(time);
+
+        // Real offsets are whole quarter hours within ±14h; the two clocks
+        // drift by a few seconds, so round to the nearest quarter hour
+        let diff_minutes = (local - utc).num_seconds() as f64 / 60.0;
+        let offset_minutes = ((diff_minutes / 15.0).round() * 15.0) as i32;
+        if offset_minutes.abs() > 14 * 60 {
+            return None;
+        }
+        FixedOffset::east_opt(offset_minutes * 60)
+    }
+
+    /// Camera, lens and exposure settings
+    pub fn camera(&self) -> CameraInfo {
+        CameraInfo {
+            make: self.ascii(Tag::Make),
+            model: self.ascii(Tag::Model),
+            lens_model: self.ascii(Tag::LensModel),
+            focal_length: self.rational(Tag::FocalLength),
+            iso: self.uint(Tag::PhotographicSensitivity),
+            aperture: self.rational(Tag::FNumber),
+            exposure_time: self.rational(Tag::ExposureTime),
+            flash_fired: self.uint(Tag::Flash).map(|flash| flash & 1 == 1),
+            orientation: self.uint(Tag::Orientation).map(|o| o as u16),
+        }
+    }
+
+    /// Where the photo was taken, if the camera recorded it
+    pub fn gps(&self) -> Option<GpsCoordinates> {
+        let latitude = self.gps_degrees(Tag::GPSLatitude, Tag::GPSLatitudeRef, "S")?;
+        let longitude = self.gps_degrees(Tag::GPSLongitude, Tag::GPSLongitudeRef, "W")?;
+
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return None;
+        }
+
+        // AltitudeRef 1 means below sea level
+        let altitude = self.rational(Tag::GPSAltitude).map(|alt| {
+            let below_sea_level = self.uint(Tag::GPSAltitudeRef) == Some(1);
+            if below_sea_level { -(alt as f64) } else { alt as f64 }
+        });
+
+        Some(GpsCoordinates { latitude, longitude, altitude })
+    }
+
+    /// Full image size as recorded by the camera. RAW files need this: their
+    /// first image is usually a small preview.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        Some((self.uint(Tag::PixelXDimension)?, self.uint(Tag::PixelYDimension)?))
+    }
+
+    /// Program that wrote the file (phones name their screenshot tool here)
+    pub fn software(&self) -> Option<String> {
+        self.ascii(Tag::Software)
+    }
+
+    /// UserComment, decoded from its 8-byte character code prefix.
+    /// iOS writes "Screenshot" here.
+    pub fn user_comment(&self) -> Option<String> {
+        let Value::Undefined(bytes, _) = &self.exif.get_field(Tag::UserComment, In::PRIMARY)?.value else {
+            return None;
+        };
+        let (code, text) = (bytes.get(..8)?, bytes.get(8..)?);
+
+        let text = if code.starts_with(b"UNICODE") {
+            // Byte order isn't specified; ASCII-range text has its zero byte first in big-endian
+            let big_endian = text.first() == Some(&0);
+            let units: Vec<u16> = text.chunks_exact(2)
+                .map(|c| if big_endian { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) })
+                .collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            String::from_utf8_lossy(text).to_string()
+        };
+
+        let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string();
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// Live Photo content identifier from Apple's MakerNote (tag 0x11). The paired
+    /// MOV carries the same value in its QuickTime metadata.
+    pub fn content_identifier(&self) -> Option<String> {
+        let Value::Undefined(note, _) = &self.exif.get_field(Tag::MakerNote, In::PRIMARY)?.value else {
+            return None;
+        };
+        apple_maker_note_string(note, 0x11)
+    }
+
+    /// Every tag in the file, formatted for display
+    pub fn all_fields(&self) -> Vec<ExifField> {
+        self.exif.fields()
+            .map(|field| ExifField {
+                tag: field.tag.to_string(),
+                ifd: field.ifd_num.to_string(),
+                value: field.display_value().with_unit(&self.exif).to_string(),
+            })
+            .collect()
+    }
+
+    fn ascii(&self, tag: Tag) -> Option<String> {
+        match &self.exif.get_field(tag, In::PRIMARY)?.value {
+            Value::Ascii(values) => {
+                let text = String::from_utf8_lossy(values.first()?).trim().to_string();
+                (!text.is_empty()).then_some(text)
+            }
+            _ => None,
+        }
+    }
+
+    fn rational(&self, tag: Tag) -> Option<f32> {
+        match &self.exif.get_field(tag, In::PRIMARY)?.value {
+            Value::Rational(values) => values.first()
+                .filter(|r| r.denom != 0)
+                .map(|r| r.to_f32()),
+            _ => None,
+        }
+    }
+
+    /// Degrees/minutes/seconds to signed decimal degrees
+    fn gps_degrees(&self, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+        let dms = match &self.exif.get_field(tag, In::PRIMARY)?.value {
+            Value::Rational(values) if values.len() >= 3 && values.iter().all(|r| r.denom != 0) => {
+                values[0].to_f64() + values[1].to_f64() / 60.0 + values[2].to_f64() / 3600.0
+            }
+            _ => return None,
+        };
+
+        let is_negative = self.ascii(ref_tag)
+            .is_some_and(|r| r.eq_ignore_ascii_case(negative_ref));
+        Some(if is_negative { -dms } else { dms })
+    }
+
+    fn uint(&self, tag: Tag) -> Option<u32> {
+        self.exif.get_field(tag, In::PRIMARY)?.value.get_uint(0)
+    }
+}
+
+/// Timezone assumed for photos that carry no offset information
+#[derive(Debug, Clone, Copy, Default)]
+pub enum HomeTimezone {
+    #[default]
+    System,                 // The computer's timezone, DST-aware
+    Named(Tz),              // An IANA zone, DST-aware
+    Fixed(FixedOffset),
+}
+
+impl HomeTimezone {
+    /// From the user's settings: an IANA zone name if given, else minutes east
+    /// of UTC, else the system timezone. Fails on an unknown zone name.
+    pub fn from_settings(name: Option<&str>, minutes: Option<i32>) -> Result<Self> {
+        match name {
+            Some(name) => name.parse()
+                .map(HomeTimezone::Named)
+                .map_err(|_| Error::Invalid(format!("Unknown timezone: {name}"))),
+            None => Ok(Self::from_offset_minutes(minutes)),
+        }
+    }
+
+    /// From a user setting in minutes east of UTC; None means the system timezone
+    pub fn from_offset_minutes(minutes: Option<i32>) -> Self {
+        minutes
+            .and_then(|m| FixedOffset::east_opt(m * 60))
+            .map(HomeTimezone::Fixed)
+            .unwrap_or_default()
+    }
+
+    /// The home zone's clock at a given instant
+    pub fn at(&self, date: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            HomeTimezone::System => date.with_timezone(&Local).fixed_offset(),
+            HomeTimezone::Named(zone) => date.with_timezone(zone).fixed_offset(),
+            HomeTimezone::Fixed(offset) => date.with_timezone(offset),
+        }
+    }
+
+    pub fn localize(&self, local: NaiveDateTime) -> DateTime<FixedOffset> {
+        match self {
+            HomeTimezone::System => localize_in(&Local, local),
+            HomeTimezone::Named(zone) => localize_in(zone, local),
+            HomeTimezone::Fixed(offset) => localize_in(offset, local),
+        }
+    }
+}
+
+fn localize_in<Z: TimeZone>(zone: &Z, local: NaiveDateTime) -> DateTime<FixedOffset> {
+    zone.from_local_datetime(&local)
+        .earliest()
+        // Inside a DST gap: that wall-clock time didn't exist, so shift past it
+        .unwrap_or_else(|| zone.from_utc_datetime(&local))
+        .fixed_offset()
+}
+
+/// ASCII entry from an Apple MakerNote: "Apple iOS\0", a version, "MM", then a
+/// big-endian IFD whose offsets count from the start of the note
+fn apple_maker_note_string(note: &[u8], tag: u16) -> Option<String> {
+    if !note.starts_with(b"Apple iOS\0") || note.get(12..14) != Some(b"MM") {
+        return None;
+    }
+    let u16_at = |at: usize| Some(u16::from_be_bytes(note.get(at..at + 2)?.try_into().ok()?));
+    let u32_at = |at: usize| Some(u32::from_be_bytes(note.get(at..at + 4)?.try_into().ok()?) as usize);
+
+    let count = u16_at(14)? as usize;
+    let entry = (0..count).map(|i| 16 + i * 12).find(|&at| u16_at(at) == Some(tag))?;
+    // Type 2 is ASCII; values over four bytes live at an offset
+    if u16_at(entry + 2)? != 2 {
+        return None;
+    }
+    let len = u32_at(entry + 4)?;
+    let start = if len > 4 { u32_at(entry + 8)? } else { entry + 8 };
+    let text = String::from_utf8_lossy(note.get(start..start.checked_add(len)?)?);
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn parse_exif_datetime(datetime_str: &str) -> Option<NaiveDateTime> {
+    // EXIF datetime format: "YYYY:MM:DD HH:MM:SS"
+    let parts: Vec<&str> = datetime_str.split(' ').collect();
+    if parts.len() != 2 {
+        return None;
+    }
+
+    let date_parts: Vec<&str> = parts[0].split(':').collect();
+    let time_parts: Vec<&str> = parts[1].split(':').collect();
+
+    if date_parts.len() != 3 || time_parts.len() != 3 {
+        return None;
+    }
+
+    let year = date_parts[0].parse::<i32>().ok()?;
+    let month = date_parts[1].parse::<u32>().ok()?;
+    let day = date_parts[2].parse::<u32>().ok()?;
+    let hour = time_parts[0].parse::<u32>().ok()?;
+    let minute = time_parts[1].parse::<u32>().ok()?;
+    let second = time_parts[2].parse::<u32>().ok()?;
+
+    NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(hour, minute, second)
+}
+
+fn parse_exif_offset(offset_str: &str) -> Option<FixedOffset> {
+    // EXIF offset format: "+HH:MM" or "-HH:MM"
+    let offset_str = offset_str.trim();
+    let sign = match offset_str.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = offset_str[1..].split_once(':')?;
+    let minutes = hours.parse::<i32>().ok()? * 60 + minutes.parse::<i32>().ok()?;
+    FixedOffset::east_opt(sign * minutes * 60)
+}
+
+#[cfg(test)]
+pub mod test_support {
+    use exif::experimental::Writer;
+    use exif::Field;
+    use std::io::Cursor;
+
+    /// Encode fields as a minimal little-endian TIFF, which kamadak-exif reads back as EXIF
+    pub fn tiff_with_fields(fields: &[Field]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, true).unwrap();
+        buf.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_support::tiff_with_fields;
+    use exif::{Field, Rational};
+    use tempfile::TempDir;
+
+    fn ascii(tag: Tag, text: &str) -> Field {
+        Field { tag, ifd_num: In::PRIMARY, value: Value::Ascii(vec![text.as_bytes().to_vec()]) }
+    }
+
+    #[test]
+    fn test_camera_info() {
+        let fields = [
+            ascii(Tag::Make, "Canon"),
+            ascii(Tag::Model, "Canon EOS R6"),
+            ascii(Tag::DateTimeOriginal, "2019:07:03 14:22:33"),
+            Field { tag: Tag::FNumber, ifd_num: In::PRIMARY, value: Value::Rational(vec![Rational { num: 28, denom: 10 }]) },
+            Field { tag: Tag::PhotographicSensitivity, ifd_num: In::PRIMARY, value: Value::Short(vec![400]) },
+            Field { tag: Tag::Flash, ifd_num: In::PRIMARY, value: Value::Short(vec![0x19]) },
+            Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![6]) },
+        ];
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("photo.tif");
+        fs::write(&path, tiff_with_fields(&fields)).unwrap();
+
+        let exif = ExifData::read(&path).unwrap().unwrap();
+        let camera = exif.camera();
+        assert_eq!(camera.make.as_deref(), Some("Canon"));
+        assert_eq!(camera.model.as_deref(), Some("Canon EOS R6"));
+        assert_eq!(camera.aperture, Some(2.8));
+        assert_eq!(camera.iso, Some(400));
+        assert_eq!(camera.flash_fired, Some(true));
+        assert_eq!(camera.orientation, Some(6));
+        assert_eq!(camera.lens_model, None);
+        let utc = HomeTimezone::Fixed(FixedOffset::east_opt(0).unwrap());
+        assert_eq!(exif.date_taken(&utc).unwrap().to_rfc3339(), "2019-07-03T14:22:33+00:00");
+        assert!(exif.all_fields().iter().any(|f| f.tag == "Model" && f.value.contains("EOS R6")));
+        assert!(exif.gps().is_none());
+    }
+
+    #[test]
+    fn test_gps_coordinates() {
+        let dms = |d, m, s| Value::Rational(vec![
+            Rational { num: d, denom: 1 },
+            Rational { num: m, denom: 1 },
+            Rational { num: s, denom: 100 },
+        ]);
+        let fields = [
+            Field { tag: Tag::GPSLatitude, ifd_num: In::PRIMARY, value: dms(40, 26, 4620) },
+            ascii(Tag::GPSLatitudeRef, "N"),
+            Field { tag: Tag::GPSLongitude, ifd_num: In::PRIMARY, value: dms(79, 58, 5600) },
+            ascii(Tag::GPSLongitudeRef, "W"),
+            Field { tag: Tag::GPSAltitude, ifd_num: In::PRIMARY, value: Value::Rational(vec![Rational { num: 12, denom: 1 }]) },
+            Field { tag: Tag::GPSAltitudeRef, ifd_num: In::PRIMARY, value: Value::Byte(vec![1]) },
+        ];
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("photo.tif");
+        fs::write(&path, tiff_with_fields(&fields)).unwrap();
+
+        let gps = ExifData::read(&path).unwrap().unwrap().gps().unwrap();
+        assert!((gps.latitude - 40.446167).abs() < 1e-5);
+        assert!((gps.longitude + 79.982222).abs() < 1e-5);
+        assert_eq!(gps.altitude, Some(-12.0));
+    }
+
+    fn date_taken(fields: &[Field], home: HomeTimezone) -> String {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("photo.tif");
+        fs::write(&path, tiff_with_fields(fields)).unwrap();
+        ExifData::read(&path).unwrap().unwrap().date_taken(&home).unwrap().to_rfc3339()
+    }
+
+    #[test]
+    fn test_date_taken_timezones() {
+        let home = HomeTimezone::Fixed(FixedOffset::east_opt(3600).unwrap());
+        let original = ascii(Tag::DateTimeOriginal, "2019:07:31 23:30:00");
+
+        // Explicit offset wins
+        let fields = [original.clone(), ascii(Tag::OffsetTimeOriginal, "-05:00")];
+        assert_eq!(date_taken(&fields, home), "2019-07-31T23:30:00-05:00");
+
+        // GPS clock says 04:29:57 UTC the next day, so the camera was at UTC-5
+        let fields = [
+            original.clone(),
+            ascii(Tag::GPSDateStamp, "2019:08:01"),
+            Field {
+                tag: Tag::GPSTimeStamp,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![
+                    Rational { num: 4, denom: 1 },
+                    Rational { num: 29, denom: 1 },
+                    Rational { num: 57, denom: 1 },
+                ]),
+            },
+        ];
+        assert_eq!(date_taken(&fields, home), "2019-07-31T23:30:00-05:00");
+
+        // Nothing else to go on: home timezone
+        assert_eq!(date_taken(std::slice::from_ref(&original), home), "2019-07-31T23:30:00+01:00");
+
+        // A named zone follows its DST rules
+        let berlin = HomeTimezone::from_settings(Some("Europe/Berlin"), Some(0)).unwrap();
+        assert_eq!(date_taken(&[original], berlin), "2019-07-31T23:30:00+02:00");
+        assert_eq!(date_taken(&[ascii(Tag::DateTimeOriginal, "2019:01:31 23:30:00")], berlin), "2019-01-31T23:30:00+01:00");
+        assert!(HomeTimezone::from_settings(Some("Europe/Atlantis"), None).is_err());
+    }
+
+    #[test]
+    fn test_apple_maker_note_content_identifier() {
+        let id = b"6F1A2B3C-0000-4D5E-8F90-ABCDEF012345\0";
+        let mut note = b"Apple iOS\0\0\x01MM".to_vec();
+        note.extend(2u16.to_be_bytes());
+        for (tag, offset) in [(0x0008u16, 0u32), (0x0011, 16 + 2 * 12 + 4)] {
+            note.extend(tag.to_be_bytes());
+            note.extend(2u16.to_be_bytes());
+            note.extend((id.len() as u32).to_be_bytes());
+            note.extend(offset.to_be_bytes());
+        }
+        note.extend(0u32.to_be_bytes()); // Next IFD
+        note.extend(id);
+
+        assert_eq!(apple_maker_note_string(&note, 0x11).as_deref(), Some("6F1A2B3C-0000-4D5E-8F90-ABCDEF012345"));
+        assert_eq!(apple_maker_note_string(&note[..20], 0x11), None);
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use crate::archives::split_entry_path;
+use crate::sessions::ScanControl;
+use crate::types::*;
+
+/// Bytes hashed from each end of a file by the prefilter
+const PARTIAL_LEN: u64 = 64 * 1024;
+
+/// Full SHA-256 of a file's contents, as lowercase hex
+pub fn content_hash(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 8192];
+
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// SHA-256 of the first and last 64 KB. Only meaningful between files of equal size.
+fn partial_hash(path: &Path, file_size: u64) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; PARTIAL_LEN as usize];
+
+    file.read_exact(&mut buffer)?;
+    hasher.update(&buffer);
+    file.seek(SeekFrom::Start(file_size - PARTIAL_LEN))?;
+    file.read_exact(&mut buffer)?;
+    hasher.update(&buffer);
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Fill in `hash` only for files that could be duplicates: bucket by size, then by
+/// partial hash, and fully hash whatever still collides. Everything else keeps `hash: None`.
+/// Files that can't be read are reported and left unhashed.
+pub fn hash_possible_duplicates(files: &mut [FileMetadata], control: &ScanControl) -> Vec<ScanError> {
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, file) in files.iter().enumerate() {
+        by_size.entry(file.file_size).or_default().push(i);
+    }
+
+    let same_size: Vec<Vec<usize>> = by_size.into_values()
+        .filter(|bucket| bucket.len() > 1 && bucket.iter().any(|&i| files[i].hash.is_none()))
+        .collect();
+
+    // Small files are read whole anyway, so they go straight to the full hash.
+    // So do files sharing a size with an archive entry, which can't be partially hashed.
+    let (small, large): (Vec<Vec<usize>>, Vec<Vec<usize>>) = same_size.into_iter()
+        .partition(|bucket| {
+            files[bucket[0]].file_size <= 2 * PARTIAL_LEN
+                || bucket.iter().any(|&i| split_entry_path(&files[i].path).is_some())
+        });
+
+    let mut errors = Vec::new();
+
+    let partials: Vec<(usize, Option<io::Result<String>>)> = large.iter()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>()
+        .par_iter()
+        .map(|&i| {
+            if !control.checkpoint() {
+                return (i, None);
+            }
+            let file = &files[i];
+            (i, Some(partial_hash(Path::new(&file.path), file.file_size)))
+        })
+        .collect();
+
+    let mut by_partial: HashMap<(u64, String), Vec<usize>> = HashMap::new();
+    for (i, partial) in partials {
+        match partial {
+            Some(Ok(partial)) => by_partial.entry((files[i].file_size, partial)).or_default().push(i),
+            Some(Err(e)) => errors.push(ScanError::new(&files[i].path, ScanStage::Hash, &e.into())),
+            None => {}
+        }
+    }
+
+    let candidates: Vec<usize> = small.into_iter()
+        .chain(by_partial.into_values().filter(|bucket| bucket.len() > 1))
+        .flatten()
+        .filter(|&i| files[i].hash.is_none())
+        .collect();
+
+    let hashes: Vec<(usize, Option<io::Result<String>>)> = candidates.par_iter()
+        .map(|&i| {
+            if !control.checkpoint() {
+                return (i, None);
+            }
+            (i, Some(content_hash(Path::new(&files[i].path))))
+        })
+        .collect();
+
+    for (i, hash) in hashes {
+        match hash {
+            Some(Ok(hash)) => files[i].hash = Some(hash),
+            Some(Err(e)) => errors.push(ScanError::new(&files[i].path, ScanStage::Hash, &e.into())),
+            None => {}
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_only_possible_duplicates_are_hashed() {
+        let dir = TempDir::new().unwrap();
+        let big = |fill: u8, middle: u8| {
+            let mut data = vec![fill; 300 * 1024];
+            data[150 * 1024] = middle;
+            data
+        };
+        let contents: Vec<(&str, Vec<u8>)> = vec![
+            ("a.jpg", big(1, 0)),
+            ("a-copy.jpg", big(1, 0)),
+            ("same-ends.jpg", big(1, 9)),   // Partial hash collides, full hash doesn't
+            ("other.jpg", big(2, 0)),       // Same size, different ends
+            ("unique.jpg", vec![1; 1000]),  // Unique size
+            ("tiny.txt", b"hello".to_vec()),
+            ("tiny-copy.txt", b"hello".to_vec()),
+        ];
+
+        let mut files: Vec<FileMetadata> = contents.iter()
+            .map(|(name, data)| {
+                let path = dir.path().join(name);
+                fs::write(&path, data).unwrap();
+                let mut file = FileMetadata::for_test(&path.to_string_lossy(), "", data.len() as u64, FileType::Image);
+                file.hash = None;
+                file
+            })
+            .collect();
+
+        let errors = hash_possible_duplicates(&mut files, &ScanControl::new("test".to_string()));
+        assert!(errors.is_empty());
+
+        let hashes: Vec<Option<&str>> = files.iter().map(|f| f.hash.as_deref()).collect();
+        assert!(hashes[0].is_some());
+        assert_eq!(hashes[0], hashes[1]);
+        assert!(hashes[2].is_some() && hashes[2] != hashes[0]);
+        assert_eq!(hashes[3], None);
+        assert_eq!(hashes[4], None);
+        assert!(hashes[5].is_some());
+        assert_eq!(hashes[5], hashes[6]);
+        assert_eq!(hashes[0].unwrap(), content_hash(Path::new(&files[0].path)).unwrap());
+    }
+}
@@ -0,0 +1,561 @@
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use crate::types::GpsCoordinates;
+
+/// Largest top-level box we'll load into memory (moov/meta are normally a few MB)
+const MAX_BOX_LEN: u64 = 64 * 1024 * 1024;
+
+/// Seconds between the QuickTime epoch (1904-01-01) and the Unix epoch
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Metadata read from an MP4/MOV/3GP `moov` box
+#[derive(Debug, Clone, Default)]
+pub struct VideoInfo {
+    pub created: Option<DateTime<Utc>>,                 // mvhd, plain UTC
+    pub created_local: Option<DateTime<FixedOffset>>,   // QuickTime creationdate, with the offset it was shot at
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration: Option<u32>,        // Seconds
+    pub rotation: u16,                // Clockwise degrees: 0, 90, 180 or 270
+    pub location: Option<GpsCoordinates>,
+    pub content_identifier: Option<String>, // Live Photo pairing key (iPhone)
+}
+
+/// Primary image of a HEIF/HEIC/AVIF file, read from its `meta` box
+#[derive(Debug, Clone, Default)]
+pub struct ImageInfo {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub rotation: u16,                // Clockwise degrees: 0, 90, 180 or 270
+    pub exif: Option<Vec<u8>>,        // TIFF-encoded EXIF block
+}
+
+/// Iterator over the boxes packed in a byte slice, yielding (type, body)
+pub struct Boxes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Boxes<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = ([u8; 4], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 8 {
+            return None;
+        }
+
+        let size = u32::from_be_bytes(self.data[0..4].try_into().ok()?) as u64;
+        let box_type: [u8; 4] = self.data[4..8].try_into().ok()?;
+
+        let (header_len, size) = match size {
+            0 => (8, self.data.len() as u64),
+            1 => (16, u64::from_be_bytes(self.data.get(8..16)?.try_into().ok()?)),
+            n => (8, n),
+        };
+
+        if size < header_len as u64 || size > self.data.len() as u64 {
+            self.data = &[];
+            return None;
+        }
+
+        let body = &self.data[header_len..size as usize];
+        self.data = &self.data[size as usize..];
+        Some((box_type, body))
+    }
+}
+
+/// Follow a path of nested box types (e.g. `[b"trak", b"tkhd"]`) and return the body
+pub fn find_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let (first, rest) = path.split_first()?;
+    let body = Boxes::new(data).find(|(t, _)| t == *first)?.1;
+    if rest.is_empty() {
+        Some(body)
+    } else {
+        find_box(body, rest)
+    }
+}
+
+/// Load the body of a top-level box without reading the (possibly huge) boxes around it
+pub fn read_top_level_box(path: &Path, box_type: &[u8; 4]) -> Option<Vec<u8>> {
+    let mut file = fs::File::open(path).ok()?;
+    let file_len = file.metadata().ok()?.len();
+    let mut offset = 0u64;
+
+    while offset + 8 <= file_len {
+        let mut header = [0u8; 16];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut header[..8]).ok()?;
+
+        let size = u32::from_be_bytes(header[0..4].try_into().ok()?) as u64;
+        let (header_len, size) = match size {
+            0 => (8, file_len - offset),
+            1 => {
+                file.read_exact(&mut header[8..16]).ok()?;
+                (16, u64::from_be_bytes(header[8..16].try_into().ok()?))
+            }
+            n => (8, n),
+        };
+
+        if size < header_len {
+            return None;
+        }
+
+        if &header[4..8] == box_type {
+            let body_len = size - header_len;
+            if body_len > MAX_BOX_LEN {
+                return None;
+            }
+            let mut body = vec![0u8; body_len as usize];
+            file.read_exact(&mut body).ok()?;
+            return Some(body);
+        }
+
+        // A crafted largesize must not wrap around or stall the walk
+        offset = offset.checked_add(size).filter(|&next| next > offset)?;
+    }
+
+    None
+}
+
+/// Read creation time, dimensions, duration, rotation and location from an MP4/MOV/3GP file
+pub fn read_video_info(path: &Path) -> Option<VideoInfo> {
+    let moov = read_top_level_box(path, b"moov")?;
+    let mut info = VideoInfo::default();
+
+    if let Some(mvhd) = find_box(&moov, &[b"mvhd"]) {
+        let (created, duration) = parse_mvhd(mvhd);
+        info.created = created;
+        info.duration = duration;
+    }
+
+    // First track with non-zero dimensions is the video track
+    for (_, trak) in Boxes::new(&moov).filter(|(t, _)| t == b"trak") {
+        if let Some((width, height, rotation)) = find_box(trak, &[b"tkhd"]).and_then(parse_tkhd) {
+            info.width = Some(width);
+            info.height = Some(height);
+            info.rotation = rotation;
+            break;
+        }
+    }
+
+    // QuickTime metadata keys (iPhone) carry a local creation date with its offset
+    let quicktime_keys = find_box(&moov, &[b"meta"]).map(parse_quicktime_metadata).unwrap_or_default();
+    for (key, value) in &quicktime_keys {
+        match key.as_str() {
+            "com.apple.quicktime.creationdate" => {
+                if let Some(created) = parse_quicktime_date(value) {
+                    info.created_local = Some(created);
+                }
+            }
+            "com.apple.quicktime.location.ISO6709" => {
+                info.location = info.location.or_else(|| parse_iso6709(value));
+            }
+            "com.apple.quicktime.content.identifier" => {
+                info.content_identifier = Some(value.clone());
+            }
+            _ => {}
+        }
+    }
+
+    // ©xyz user data atom (Android and older iOS)
+    if info.location.is_none() {
+        info.location = find_box(&moov, &[b"udta", b"\xA9xyz"])
+            .and_then(|xyz| xyz.get(4..))
+            .and_then(|text| parse_iso6709(&String::from_utf8_lossy(text)));
+    }
+
+    Some(info)
+}
+
+/// Read dimensions, rotation and the EXIF block of a HEIF/HEIC/AVIF image without decoding it
+pub fn read_image_info(path: &Path) -> Option<ImageInfo> {
+    let meta = read_top_level_box(path, b"meta")?;
+    let meta = meta.get(4..)?; // version + flags
+    let pitm = find_box(meta, &[b"pitm"])?;
+    let primary = if pitm.first()? == &0 { read_u16(pitm, 4)? as u32 } else { read_u32(pitm, 4)? };
+    let mut info = ImageInfo::default();
+
+    // ipco holds the properties, ipma says which ones (1-based) each item uses
+    let ipco: Vec<([u8; 4], &[u8])> = find_box(meta, &[b"iprp", b"ipco"]).map(|b| Boxes::new(b).collect()).unwrap_or_default();
+    let used = find_box(meta, &[b"iprp", b"ipma"]).and_then(|ipma| item_properties(ipma, primary)).unwrap_or_default();
+    for (box_type, body) in used.iter().filter_map(|&i| ipco.get(i.checked_sub(1)?)) {
+        match box_type {
+            // Sizes of a grid (as iPhones write) are those of the whole image
+            b"ispe" => {
+                info.width = read_u32(body, 4);
+                info.height = read_u32(body, 8);
+            }
+            // Quarter turns anti-clockwise
+            b"irot" => info.rotation = (4 - u16::from(body.first().copied().unwrap_or(0) & 3)) % 4 * 90,
+            _ => {}
+        }
+    }
+
+    // The Exif item starts with the offset of the TIFF header that follows it
+    info.exif = exif_item(meta)
+        .and_then(|id| item_data(path, meta, id))
+        .and_then(|data| data.get(4 + read_u32(&data, 0)? as usize..).map(<[u8]>::to_vec));
+
+    Some(info)
+}
+
+/// Property indices an item uses, from `ipma`
+fn item_properties(ipma: &[u8], item: u32) -> Option<Vec<usize>> {
+    let version = *ipma.first()?;
+    let wide_index = ipma.get(3)? & 1 == 1;
+    let mut at = 8;
+    for _ in 0..read_u32(ipma, 4)? {
+        let id = if version < 1 { read_u16(ipma, at)? as u32 } else { read_u32(ipma, at)? };
+        at += if version < 1 { 2 } else { 4 };
+        let count = *ipma.get(at)? as usize;
+        at += 1;
+        // Top bit of each entry marks the property essential
+        let indices = (0..count).map(|i| if wide_index {
+            read_u16(ipma, at + i * 2).map(|v| (v & 0x7fff) as usize)
+        } else {
+            ipma.get(at + i).map(|v| (v & 0x7f) as usize)
+        });
+        if id == item {
+            return indices.collect();
+        }
+        at += count * if wide_index { 2 } else { 1 };
+    }
+    None
+}
+
+/// Id of the Exif item, from the `infe` entries in `iinf`
+fn exif_item(meta: &[u8]) -> Option<u32> {
+    let iinf = find_box(meta, &[b"iinf"])?;
+    let entries = iinf.get(if iinf.first()? == &0 { 6 } else { 8 }..)?;
+    Boxes::new(entries)
+        .filter(|(t, _)| t == b"infe")
+        .find_map(|(_, infe)| {
+            // Versions 2 and 3 differ in the width of the item id
+            let (id, type_at) = match infe.first()? {
+                2 => (read_u16(infe, 4)? as u32, 8),
+                3 => (read_u32(infe, 4)?, 10),
+                _ => return None,
+            };
+            (infe.get(type_at..type_at + 4)? == b"Exif").then_some(id)
+        })
+}
+
+/// An item's bytes, located through `iloc` either in the file or in `idat`
+fn item_data(path: &Path, meta: &[u8], item: u32) -> Option<Vec<u8>> {
+    let iloc = find_box(meta, &[b"iloc"])?;
+    let version = *iloc.first()?;
+    let (offset_size, length_size) = (iloc.get(4)? >> 4, iloc.get(4)? & 15);
+    let (base_offset_size, index_size) = (iloc.get(5)? >> 4, if version > 0 { iloc.get(5)? & 15 } else { 0 });
+
+    let mut at = 6;
+    let item_count = if version < 2 { read_u16(iloc, at)? as u32 } else { read_u32(iloc, at)? };
+    at += if version < 2 { 2 } else { 4 };
+
+    for _ in 0..item_count {
+        let id = if version < 2 { read_u16(iloc, at)? as u32 } else { read_u32(iloc, at)? };
+        at += if version < 2 { 2 } else { 4 };
+        let method = if version > 0 { read_u16(iloc, at)? & 15 } else { 0 };
+        at += if version > 0 { 2 } else { 0 } + 2; // construction method, data reference index
+        let base = read_sized(iloc, &mut at, base_offset_size)?;
+        let extent_count = read_u16(iloc, at)?;
+        at += 2;
+
+        let mut extents = Vec::new();
+        for _ in 0..extent_count {
+            read_sized(iloc, &mut at, index_size)?;
+            let offset = base.checked_add(read_sized(iloc, &mut at, offset_size)?)?;
+            extents.push((offset, read_sized(iloc, &mut at, length_size)?));
+        }
+        if id != item {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        for (offset, length) in extents {
+            if (data.len() as u64).checked_add(length)? > MAX_BOX_LEN {
+                return None;
+            }
+            match method {
+                0 => {
+                    let mut file = fs::File::open(path).ok()?;
+                    file.seek(SeekFrom::Start(offset)).ok()?;
+                    file.take(length).read_to_end(&mut data).ok()?;
+                }
+                1 => {
+                    let end = usize::try_from(offset.checked_add(length)?).ok()?;
+                    data.extend_from_slice(find_box(meta, &[b"idat"])?.get(usize::try_from(offset).ok()?..end)?);
+                }
+                _ => return None,
+            }
+        }
+        return Some(data);
+    }
+    None
+}
+
+/// Big-endian integer of 0, 4 or 8 bytes, as sized by `iloc`
+fn read_sized(data: &[u8], at: &mut usize, size: u8) -> Option<u64> {
+    let value = match size {
+        0 => 0,
+        4 => read_u32(data, *at)? as u64,
+        8 => read_u64(data, *at)?,
+        _ => return None,
+    };
+    *at += size as usize;
+    Some(value)
+}
+
+fn parse_mvhd(mvhd: &[u8]) -> (Option<DateTime<Utc>>, Option<u32>) {
+    let version = mvhd.first().copied().unwrap_or(0);
+    let (created, timescale, duration) = if version == 1 {
+        (read_u64(mvhd, 4), read_u32(mvhd, 20), read_u64(mvhd, 24))
+    } else {
+        (read_u32(mvhd, 4).map(u64::from), read_u32(mvhd, 12), read_u32(mvhd, 16).map(u64::from))
+    };
+
+    // Many Android phones leave the creation time at zero
+    let created = created
+        .filter(|&secs| secs > 0)
+        .and_then(|secs| Utc.timestamp_opt(secs as i64 - QUICKTIME_EPOCH_OFFSET, 0).single());
+
+    let duration = match (timescale, duration) {
+        (Some(scale), Some(duration)) if scale > 0 => Some((duration / scale as u64) as u32),
+        _ => None,
+    };
+
+    (created, duration)
+}
+
+fn parse_tkhd(tkhd: &[u8]) -> Option<(u32, u32, u16)> {
+    let version = *tkhd.first()?;
+    // Matrix starts after the version-dependent times/duration and fixed fields
+    let matrix_offset = if version == 1 { 52 } else { 40 };
+
+    let a = read_u32(tkhd, matrix_offset)? as i32;
+    let b = read_u32(tkhd, matrix_offset + 4)? as i32;
+    let width = read_u32(tkhd, matrix_offset + 36)? >> 16;
+    let height = read_u32(tkhd, matrix_offset + 40)? >> 16;
+
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    // 16.16 fixed-point rotation matrix: [a b; c d]
+    let one = 1 << 16;
+    let rotation = match (a, b) {
+        (0, b) if b == one => 90,
+        (a, 0) if a == -one => 180,
+        (0, b) if b == -one => 270,
+        _ => 0,
+    };
+
+    Some((width, height, rotation))
+}
+
+/// QuickTime `meta` box: `keys` names each entry, `ilst` holds values by 1-based key index
+fn parse_quicktime_metadata(meta: &[u8]) -> Vec<(String, String)> {
+    let keys: Vec<String> = find_box(meta, &[b"keys"])
+        .map(|keys| {
+            let mut names = Vec::new();
+            let mut rest = keys.get(8..).unwrap_or_default();
+            while rest.len() >= 8 {
+                let size = read_u32(rest, 0).unwrap_or(0) as usize;
+                if size < 8 || size > rest.len() {
+                    break;
+                }
+                names.push(String::from_utf8_lossy(&rest[8..size]).to_string());
+                rest = &rest[size..];
+            }
+            names
+        })
+        .unwrap_or_default();
+
+    let Some(ilst) = find_box(meta, &[b"ilst"]) else {
+        return Vec::new();
+    };
+
+    Boxes::new(ilst)
+        .filter_map(|(index, item)| {
+            let key = keys.get((u32::from_be_bytes(index) as usize).checked_sub(1)?)?;
+            // data box: type (4), locale (4), value
+            let data = find_box(item, &[b"data"])?;
+            let value = String::from_utf8_lossy(data.get(8..)?).to_string();
+            Some((key.clone(), value))
+        })
+        .collect()
+}
+
+/// e.g. "2019-07-31T23:30:00-0500"
+fn parse_quicktime_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim_end_matches('\0');
+    DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%z")
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .ok()
+}
+
+/// ISO 6709 point, e.g. "+37.3349-122.0090+030.000/"
+fn parse_iso6709(value: &str) -> Option<GpsCoordinates> {
+    let value = value.trim_end_matches(['\0', '/']);
+    let starts: Vec<usize> = value.char_indices()
+        .filter(|(_, c)| *c == '+' || *c == '-')
+        .map(|(i, _)| i)
+        .collect();
+
+    let part = |n: usize| -> Option<f64> {
+        let start = *starts.get(n)?;
+        let end = starts.get(n + 1).copied().unwrap_or(value.len());
+        value[start..end].parse().ok()
+    };
+
+    let latitude = part(0)?;
+    let longitude = part(1)?;
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return None;
+    }
+
+    Some(GpsCoordinates { latitude, longitude, altitude: part(2) })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/// Convert a video rotation to the equivalent EXIF orientation value
+pub fn rotation_to_orientation(rotation: u16) -> u16 {
+    match rotation {
+        90 => 6,
+        180 => 3,
+        270 => 8,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+pub mod test_support {
+    /// Wrap a body in a box header
+    pub fn make_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(box_type);
+        out.extend_from_slice(body);
+        out
+    }
+
+    /// Movie header created at `created_unix`, lasting `duration` ticks of `timescale`
+    pub fn mvhd(created_unix: i64, timescale: u32, duration: u32) -> Vec<u8> {
+        let mut body = vec![0u8; 4]; // version + flags
+        body.extend(((created_unix + super::QUICKTIME_EPOCH_OFFSET) as u32).to_be_bytes());
+        body.extend(0u32.to_be_bytes()); // modification time
+        body.extend(timescale.to_be_bytes());
+        body.extend(duration.to_be_bytes());
+        body.extend([0u8; 80]);
+        make_box(b"mvhd", &body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_support::{make_box, mvhd};
+    use crate::exif_data::test_support::tiff_with_fields;
+    use exif::{Field, In, Tag, Value};
+    use tempfile::TempDir;
+
+    fn tkhd(width: u32, height: u32, rotated_90: bool) -> Vec<u8> {
+        let mut body = vec![0u8; 40];
+        let one: i32 = 1 << 16;
+        let matrix: [i32; 9] = if rotated_90 {
+            [0, one, 0, -one, 0, 0, 0, 0, 1 << 30]
+        } else {
+            [one, 0, 0, 0, one, 0, 0, 0, 1 << 30]
+        };
+        for value in matrix {
+            body.extend(value.to_be_bytes());
+        }
+        body.extend((width << 16).to_be_bytes());
+        body.extend((height << 16).to_be_bytes());
+        make_box(b"tkhd", &body)
+    }
+
+    #[test]
+    fn test_read_video_info() {
+        let mut xyz = vec![0u8; 4];
+        xyz.extend_from_slice(b"+37.3349-122.0090+030.000/");
+
+        let mut moov = mvhd(1_564_615_800, 600, 600 * 42);
+        moov.extend(make_box(b"trak", &tkhd(0, 0, false))); // audio track
+        moov.extend(make_box(b"trak", &tkhd(1920, 1080, true)));
+        moov.extend(make_box(b"udta", &make_box(b"\xA9xyz", &xyz)));
+
+        let mut file = make_box(b"ftyp", b"qt  \0\0\0\0");
+        file.extend(make_box(b"mdat", &[0u8; 1024]));
+        file.extend(make_box(b"moov", &moov));
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("clip.mov");
+        fs::write(&path, file).unwrap();
+
+        let info = read_video_info(&path).unwrap();
+        assert_eq!(info.created.unwrap().to_rfc3339(), "2019-07-31T23:30:00+00:00");
+        assert_eq!(info.duration, Some(42));
+        assert_eq!((info.width, info.height, info.rotation), (Some(1920), Some(1080), 90));
+        let location = info.location.unwrap();
+        assert_eq!((location.latitude, location.longitude, location.altitude), (37.3349, -122.009, Some(30.0)));
+    }
+
+    #[test]
+    fn test_read_image_info() {
+        let tiff = tiff_with_fields(&[Field { tag: Tag::Make, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Apple".to_vec()]) }]);
+        let exif_item = [&0u32.to_be_bytes()[..], &tiff].concat(); // TIFF header right after the offset
+
+        let full = |version: u8, body: &[u8]| [&[version, 0, 0, 0][..], body].concat();
+        let infe = |id: u16, item_type: &[u8; 4]| make_box(b"infe", &full(2, &[&id.to_be_bytes()[..], &[0, 0], item_type].concat()));
+        let meta = |exif_offset: u32| {
+            let iinf = [2u16.to_be_bytes().to_vec(), infe(1, b"grid"), infe(2, b"Exif")].concat();
+            // 4-byte offsets and lengths, no base offset; item 2 has one extent
+            let iloc = [&[0x44, 0x00][..], &1u16.to_be_bytes(), &2u16.to_be_bytes(), &0u16.to_be_bytes(),
+                &1u16.to_be_bytes(), &exif_offset.to_be_bytes(), &(exif_item.len() as u32).to_be_bytes()].concat();
+            let ispe = make_box(b"ispe", &full(0, &[&4032u32.to_be_bytes()[..], &3024u32.to_be_bytes()].concat()));
+            let ipco = [ispe, make_box(b"irot", &[3])].concat();
+            let ipma = full(0, &[&1u32.to_be_bytes()[..], &1u16.to_be_bytes(), &[2, 0x81, 0x02]].concat());
+            let iprp = [make_box(b"ipco", &ipco), make_box(b"ipma", &ipma)].concat();
+            make_box(b"meta", &full(0, &[
+                make_box(b"pitm", &full(0, &1u16.to_be_bytes())),
+                make_box(b"iinf", &full(0, &iinf)),
+                make_box(b"iloc", &full(0, &iloc)),
+                make_box(b"iprp", &iprp),
+            ].concat()))
+        };
+        let ftyp = make_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+        let exif_offset = (ftyp.len() + meta(0).len() + 8) as u32;
+        let file = [ftyp, meta(exif_offset), make_box(b"mdat", &exif_item)].concat();
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("IMG_0001.HEIC");
+        fs::write(&path, file).unwrap();
+
+        let info = read_image_info(&path).unwrap();
+        assert_eq!((info.width, info.height, info.rotation), (Some(4032), Some(3024), 90));
+        assert_eq!(info.exif.as_deref(), Some(&tiff[..]));
+    }
+
+    #[test]
+    fn test_parse_quicktime_date() {
+        let date = parse_quicktime_date("2019-07-31T23:30:00-0500").unwrap();
+        assert_eq!(date.to_rfc3339(), "2019-07-31T23:30:00-05:00");
+    }
+}
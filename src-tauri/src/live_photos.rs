@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use crate::types::*;
+
+/// Longest clip that still counts as the motion half of a Live Photo when the
+/// pair can only be matched by name
+const MAX_LIVE_CLIP_SECONDS: u32 = 5;
+
+/// Bytes searched at each end of a JPEG for motion photo markers
+const MARKER_SEARCH_LEN: u64 = 128 * 1024;
+
+/// Whether a JPEG has a video embedded after the image: Google motion photos and
+/// micro videos announce it in their XMP, Samsung appends a tagged trailer
+pub fn is_motion_photo(path: &Path) -> bool {
+    let Ok(mut file) = fs::File::open(path) else {
+        return false;
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+    let mut head = Vec::new();
+    if file.by_ref().take(MARKER_SEARCH_LEN).read_to_end(&mut head).is_err() {
+        return false;
+    }
+    let mut tail = Vec::new();
+    if len > MARKER_SEARCH_LEN {
+        let tail_start = len.saturating_sub(MARKER_SEARCH_LEN).max(MARKER_SEARCH_LEN);
+        if file.seek(SeekFrom::Start(tail_start)).is_ok() {
+            let _ = file.read_to_end(&mut tail);
+        }
+    }
+
+    let contains = |data: &[u8], marker: &[u8]| data.windows(marker.len()).any(|w| w == marker);
+    [&b"Camera:MotionPhoto=\"1\""[..], b"Camera:MicroVideo=\"1\"", b"<GCamera:MotionPhoto>1<"]
+        .iter()
+        .any(|marker| contains(&head, marker))
+        || contains(&tail, b"MotionPhoto_Data")
+}
+
+/// Link the still and video halves of Live Photos through `paired_with`. Halves
+/// are matched by their shared content identifier, or failing that by a short
+/// clip with the same name in the same folder.
+pub fn pair_live_photos(files: &mut [FileMetadata]) {
+    for file in files.iter_mut() {
+        file.paired_with = None;
+    }
+
+    let key = |file: &FileMetadata| {
+        let path = Path::new(&file.path);
+        let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+        Some((path.parent()?.to_path_buf(), stem))
+    };
+
+    let mut stills_by_id: HashMap<&str, usize> = HashMap::new();
+    let mut stills_by_name = HashMap::new();
+    for (i, file) in files.iter().enumerate().filter(|(_, f)| matches!(f.file_type, FileType::Image)) {
+        if let Some(id) = file.content_identifier.as_deref() {
+            stills_by_id.insert(id, i);
+        }
+        if let Some(key) = key(file) {
+            stills_by_name.insert(key, i);
+        }
+    }
+
+    let pairs: Vec<(usize, usize)> = files.iter()
+        .enumerate()
+        .filter(|(_, f)| matches!(f.file_type, FileType::Video))
+        .filter_map(|(i, video)| {
+            let by_id = video.content_identifier.as_deref().and_then(|id| stills_by_id.get(id));
+            let by_name = || {
+                let still = *stills_by_name.get(&key(video)?)?;
+                // Identifiers on both sides that disagree mean two unrelated files
+                let conflicting = video.content_identifier.is_some()
+                    && files[still].content_identifier.is_some();
+                let short = video.duration.is_none_or(|d| d <= MAX_LIVE_CLIP_SECONDS);
+                (short && !conflicting).then_some(still)
+            };
+            Some((by_id.copied().or_else(by_name)?, i))
+        })
+        .collect();
+
+    for (still, video) in pairs {
+        // First clip wins if two claim the same still
+        if files[still].paired_with.is_some() {
+            continue;
+        }
+        files[still].paired_with = Some(files[video].path.clone());
+        files[video].paired_with = Some(files[still].path.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pair_live_photos() {
+        let file = |path: &str, file_type: FileType, id: Option<&str>, duration: Option<u32>| {
+            let mut file = FileMetadata::for_test(path, "", 10, file_type);
+            file.content_identifier = id.map(str::to_string);
+            file.duration = duration;
+            file
+        };
+
+        let mut files = vec![
+            // Renamed on export, still paired by identifier
+            file("/p/IMG_0001.HEIC", FileType::Image, Some("A1B2"), None),
+            file("/p/export/clip.mov", FileType::Video, Some("A1B2"), Some(3)),
+            // No identifiers: same name and folder, short clip
+            file("/p/IMG_0002.JPG", FileType::Image, None, None),
+            file("/p/IMG_0002.MOV", FileType::Video, None, Some(2)),
+            // Same name, but a real video
+            file("/p/IMG_0003.JPG", FileType::Image, None, None),
+            file("/p/IMG_0003.MOV", FileType::Video, None, Some(40)),
+            // Same name, different identifiers
+            file("/p/IMG_0004.HEIC", FileType::Image, Some("C3"), None),
+            file("/p/IMG_0004.MOV", FileType::Video, Some("D4"), Some(3)),
+        ];
+        pair_live_photos(&mut files);
+
+        let paired: Vec<Option<&str>> = files.iter().map(|f| f.paired_with.as_deref()).collect();
+        assert_eq!(paired, vec![
+            Some("/p/export/clip.mov"), Some("/p/IMG_0001.HEIC"),
+            Some("/p/IMG_0002.MOV"), Some("/p/IMG_0002.JPG"),
+            None, None,
+            None, None,
+        ]);
+    }
+}
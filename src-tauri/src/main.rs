@@ -6,9 +6,31 @@ mod types;
 mod commands;
 mod organizer;
 mod executor;
+mod catalog;
+mod sessions;
+mod detect;
+mod dates;
+mod exif_data;
+mod isobmff;
+mod similarity;
+mod duplicates;
+mod resolver;
+mod hashing;
+mod quality;
+mod bursts;
+mod screenshots;
+mod downloads;
+mod live_photos;
+mod error;
+mod scan_options;
+mod raw;
+mod companions;
+mod archives;
 
 use commands::*;
-use tauri::{Emitter, menu::{Menu, MenuItem, Submenu, PredefinedMenuItem}};
+use catalog::Catalog;
+use std::sync::Mutex;
+use tauri::{Emitter, Manager, menu::{Menu, MenuItem, Submenu, PredefinedMenuItem}};
 
 fn main() {
     tauri::Builder::default()
@@ -16,6 +38,11 @@ fn main() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
         .setup(|app| {
+            // Open the photo catalog
+            let catalog = Catalog::open(app.path().app_data_dir()?.join("catalog.db"))?;
+            app.manage(Mutex::new(catalog));
+            app.manage(sessions::ScanSessions::default());
+
             // Create menu
             
             // App menu (macOS)
@@ -85,7 +112,20 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             greet,
             scan_directory,
+            cancel_scan,
+            pause_scan,
+            resume_scan,
             get_scan_stats,
+            get_scan_history,
+            classify_downloads,
+            get_default_download_rules,
+            get_file_details,
+            find_similar_images,
+            find_duplicate_groups,
+            find_photo_bursts,
+            resolve_duplicates,
+            undo_resolution,
+            get_resolution_history,
             create_organization_plan,
             execute_organization,
             get_home_dir,
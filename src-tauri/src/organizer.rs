@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::path::Path;
-use chrono::{DateTime, Datelike, Utc};
+use chrono::{DateTime, Datelike, FixedOffset, Local};
+use crate::error::{Error, Result};
 use crate::types::*;
 
 /// Generate month name from number
@@ -22,39 +23,53 @@ fn get_month_name(month: u32) -> &'static str {
     }
 }
 
-/// Get the best available date from file metadata
-fn get_file_date(file: &FileMetadata) -> Option<DateTime<Utc>> {
-    // Priority: date_taken > created_at > modified_at
-    file.date_taken
-        .or(file.created_at)
-        .or(file.modified_at)
+/// Get the best available date from file metadata, on the local calendar
+fn get_file_date(file: &FileMetadata) -> Option<DateTime<FixedOffset>> {
+    // Priority: date_taken > created_at > modified_at.
+    // Capture times keep the offset they were taken at; anything else
+    // is shown in the computer's timezone, as a file browser would.
+    file.local_date_taken().or_else(|| {
+        file.date_taken
+            .or(file.created_at)
+            .or(file.modified_at)
+            .map(|date| date.with_timezone(&Local).fixed_offset())
+    })
 }
 
 /// Generate organization plan based on strategy
 pub fn generate_organization_plan(
     files: Vec<FileMetadata>,
+    source_root: String,
     destination_root: String,
     strategy: OrganizationStrategy,
     mode: OperationMode,
-) -> Result<OrganizationPlan, String> {
+) -> Result<OrganizationPlan> {
     if files.is_empty() {
-        return Err("No files to organize".to_string());
+        return Err(Error::NothingToOrganize);
     }
 
     // Check if destination path is valid
     let dest_path = Path::new(&destination_root);
     if !dest_path.exists() {
-        return Err(format!("Destination path does not exist: {destination_root}"));
+        return Err(Error::DestinationMissing(destination_root));
     }
 
     let mut folders_map: HashMap<String, Vec<FileMetadata>> = HashMap::new();
     let mut files_without_dates = 0;
 
+    // The clip of a Live Photo follows its still and a RAW follows its JPEG,
+    // so neither pair splits up
+    let leads: HashMap<String, FileMetadata> = files.iter()
+        .filter(|f| matches!(f.file_type, FileType::Image))
+        .flat_map(|f| f.paired_with.iter().chain(&f.companions).map(move |p| (p.clone(), f.clone())))
+        .collect();
+
     // Group files by destination folder
     for file in files {
+        let anchor = leads.get(&file.path).unwrap_or(&file);
         let folder_path = match &strategy {
             OrganizationStrategy::Date => {
-                if let Some(date) = get_file_date(&file) {
+                if let Some(date) = get_file_date(anchor) {
                     format!(
                         "{}/{:04}/{:02}-{}",
                         destination_root,
@@ -68,7 +83,7 @@ pub fn generate_organization_plan(
                 }
             }
             OrganizationStrategy::Year => {
-                if let Some(date) = get_file_date(&file) {
+                if let Some(date) = get_file_date(anchor) {
                     format!("{destination_root}/{:04}", date.year())
                 } else {
                     files_without_dates += 1;
@@ -76,7 +91,7 @@ pub fn generate_organization_plan(
                 }
             }
             OrganizationStrategy::YearMonth => {
-                if let Some(date) = get_file_date(&file) {
+                if let Some(date) = get_file_date(anchor) {
                     format!("{destination_root}/{:04}/{:02}", date.year(), date.month())
                 } else {
                     files_without_dates += 1;
@@ -84,7 +99,7 @@ pub fn generate_organization_plan(
                 }
             }
             OrganizationStrategy::FileType => {
-                let type_folder = match file.file_type {
+                let type_folder = match anchor.file_type {
                     FileType::Image => "Images",
                     FileType::Video => "Videos",
                     FileType::Document => "Documents",
@@ -95,8 +110,8 @@ pub fn generate_organization_plan(
                 format!("{destination_root}/{type_folder}")
             }
             OrganizationStrategy::DateAndType => {
-                if let Some(date) = get_file_date(&file) {
-                    let type_folder = match file.file_type {
+                if let Some(date) = get_file_date(anchor) {
+                    let type_folder = match anchor.file_type {
                         FileType::Image => "Images",
                         FileType::Video => "Videos",
                         FileType::Document => "Documents",
@@ -114,7 +129,7 @@ pub fn generate_organization_plan(
                     )
                 } else {
                     files_without_dates += 1;
-                    let type_folder = match file.file_type {
+                    let type_folder = match anchor.file_type {
                         FileType::Image => "Images",
                         FileType::Video => "Videos",
                         FileType::Document => "Documents",
@@ -140,12 +155,14 @@ pub fn generate_organization_plan(
             let file_count = files.len();
             let total_size: u64 = files.iter().map(|f| f.file_size).sum();
             let file_names: Vec<String> = files.iter().map(|f| f.file_name.clone()).collect();
+            let sources: Vec<String> = files.iter().map(|f| f.path.clone()).collect();
 
             FolderPreview {
                 path,
                 file_count,
                 total_size,
                 files: file_names,
+                sources,
             }
         })
         .collect();
@@ -157,6 +174,7 @@ pub fn generate_organization_plan(
     let total_size = folders.iter().map(|f| f.total_size).sum();
 
     Ok(OrganizationPlan {
+        source_root,
         destination_root,
         strategy,
         mode,
@@ -177,4 +195,25 @@ mod tests {
         assert_eq!(get_month_name(12), "December");
         assert_eq!(get_month_name(13), "Unknown");
     }
+
+    #[test]
+    fn test_date_buckets_use_capture_offset() {
+        let dest = tempfile::TempDir::new().unwrap();
+        let dest_root = dest.path().to_string_lossy().to_string();
+
+        // 23:30 on July 31st in UTC-5 is already August 1st in UTC
+        let mut file = FileMetadata::for_test("/p/a.jpg", "a", 10, FileType::Image);
+        file.date_taken = Some("2019-08-01T04:30:00Z".parse().unwrap());
+        file.date_taken_offset = Some(-300);
+
+        let plan = generate_organization_plan(
+            vec![file],
+            "/p".to_string(),
+            dest_root.clone(),
+            OrganizationStrategy::Date,
+            OperationMode::Copy,
+        )
+        .unwrap();
+        assert_eq!(plan.folders[0].path, format!("{dest_root}/2019/07-July"));
+    }
 }
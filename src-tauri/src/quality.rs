@@ -0,0 +1,131 @@
+use std::path::Path;
+use image::{DynamicImage, GrayImage};
+use crate::error::Result;
+use crate::types::Exposure;
+
+/// Images are scaled to fit this box before analysis, so scores are fast and
+/// comparable between a 12 MP original and its resized copy
+const ANALYSIS_SIZE: u32 = 512;
+
+/// Laplacian variance below which a photo counts as blurry
+pub const BLUR_THRESHOLD: f32 = 100.0;
+
+/// Laplacian variance treated as perfectly sharp when scoring
+const SHARP_REFERENCE: f64 = 500.0;
+
+/// Luma at or below / at or above which a pixel is crushed shadow / blown highlight
+const SHADOW_LEVEL: u8 = 24;
+const HIGHLIGHT_LEVEL: u8 = 232;
+
+/// Share of clipped pixels that makes a photo under- or overexposed
+const CLIPPED_LIMIT: f64 = 0.5;
+
+/// Result of the CPU-only quality pass over one image
+#[derive(Debug, Clone, Copy)]
+pub struct ImageQuality {
+    pub sharpness: f32,
+    pub exposure: Exposure,
+    pub score: f32,         // 0-1, sharpness and exposure combined
+}
+
+/// Decode an image, guessing the format from content since the extension may lie
+pub fn decode_image(path: &Path) -> Result<DynamicImage> {
+    Ok(image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .decode()?)
+}
+
+/// Score sharpness and exposure on a shared greyscale thumbnail
+pub fn analyze(image: &DynamicImage) -> ImageQuality {
+    let grey = thumbnail(image);
+    let sharpness = laplacian_variance(&grey);
+
+    let total = grey.pixels().len().max(1) as f64;
+    let shadows = grey.pixels().filter(|p| p[0] <= SHADOW_LEVEL).count() as f64 / total;
+    let highlights = grey.pixels().filter(|p| p[0] >= HIGHLIGHT_LEVEL).count() as f64 / total;
+
+    let exposure = if shadows >= CLIPPED_LIMIT {
+        Exposure::Under
+    } else if highlights >= CLIPPED_LIMIT {
+        Exposure::Over
+    } else {
+        Exposure::Good
+    };
+
+    // Some clipping is normal (night skies, snow); only penalise it past 10%
+    let exposure_score = (1.0 - (shadows - 0.1).max(0.0) - (highlights - 0.1).max(0.0)).clamp(0.0, 1.0);
+    let sharpness_score = (sharpness / SHARP_REFERENCE).min(1.0);
+
+    ImageQuality {
+        sharpness: sharpness as f32,
+        exposure,
+        score: (0.6 * sharpness_score + 0.4 * exposure_score) as f32,
+    }
+}
+
+/// Variance of the Laplacian over a greyscale thumbnail: high for crisp edges, low for blur
+pub fn sharpness(image: &DynamicImage) -> f64 {
+    laplacian_variance(&thumbnail(image))
+}
+
+fn thumbnail(image: &DynamicImage) -> GrayImage {
+    image.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).to_luma8()
+}
+
+fn laplacian_variance(grey: &GrayImage) -> f64 {
+    let (width, height) = grey.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let pixel = |x: u32, y: u32| f64::from(grey.get_pixel(x, y)[0]);
+    let mut sum = 0.0;
+    let mut sum_sq = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1)
+                - 4.0 * pixel(x, y);
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+        }
+    }
+
+    let count = f64::from((width - 2) * (height - 2));
+    let mean = sum / count;
+    sum_sq / count - mean * mean
+}
+
+/// Decode an image and score its sharpness
+pub fn sharpness_of(path: &Path) -> Option<f64> {
+    Some(sharpness(&decode_image(path).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma};
+
+    #[test]
+    fn test_blur_lowers_sharpness() {
+        let checkerboard = DynamicImage::ImageLuma8(ImageBuffer::from_fn(256, 256, |x, y| {
+            Luma([if (x / 8 + y / 8) % 2 == 0 { 255 } else { 0 }])
+        }));
+        let blurred = checkerboard.blur(4.0);
+
+        assert!(sharpness(&checkerboard) > 10.0 * sharpness(&blurred));
+    }
+
+    #[test]
+    fn test_exposure() {
+        let flat = |level: u8| DynamicImage::ImageLuma8(ImageBuffer::from_pixel(64, 64, Luma([level])));
+
+        assert!(matches!(analyze(&flat(5)).exposure, Exposure::Under));
+        assert!(matches!(analyze(&flat(250)).exposure, Exposure::Over));
+        assert!(matches!(analyze(&flat(128)).exposure, Exposure::Good));
+
+        // A flat grey frame is well exposed but has no detail at all
+        let grey = analyze(&flat(128));
+        assert!(grey.sharpness < BLUR_THRESHOLD);
+        assert!(grey.score < 0.5);
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use exif::{Exif, Reader};
+use crate::error::Result;
+use crate::isobmff::{find_box, read_top_level_box, Boxes};
+
+/// How much of a TIFF-like RAW is loaded to parse its IFDs. The tags and the
+/// MakerNote sit near the start; the sensor data after them is never needed.
+const RAW_HEADER_LEN: u64 = 4 * 1024 * 1024;
+
+/// Canon's CR3 metadata container inside `moov`
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+/// EXIF from RAW formats kamadak-exif can't open by itself: Olympus ORF and
+/// Panasonic RW2 are TIFF with a private magic number, Fujifilm RAF wraps a JPEG
+/// preview, Canon CR3 keeps TIFF blocks in ISOBMFF boxes. CR2, NEF, ARW and DNG
+/// are plain TIFF and never get here. Ok(None) for anything else.
+pub fn read_exif(path: &Path) -> Result<Option<Exif>> {
+    let mut file = fs::File::open(path)?;
+    let mut header = [0u8; 92];
+    let len = file.read(&mut header)?;
+    let header = &header[..len];
+
+    if header.starts_with(b"IIRO") || header.starts_with(b"IIRS") || header.starts_with(b"IIU\0") {
+        let data = read_head(&mut file, [b'I', b'I', 42, 0])?;
+        Ok(Some(Reader::new().read_raw(data)?))
+    } else if header.starts_with(b"MMOR") {
+        let data = read_head(&mut file, [b'M', b'M', 0, 42])?;
+        Ok(Some(Reader::new().read_raw(data)?))
+    } else if header.starts_with(b"FUJIFILMCCD-RAW") && header.len() >= 92 {
+        let offset = u32::from_be_bytes(header[84..88].try_into().unwrap_or_default()) as u64;
+        let length = u32::from_be_bytes(header[88..92].try_into().unwrap_or_default()) as u64;
+        let mut jpeg = Vec::new();
+        file.seek(SeekFrom::Start(offset))?;
+        file.take(length.min(RAW_HEADER_LEN)).read_to_end(&mut jpeg)?;
+        Ok(Some(Reader::new().read_from_container(&mut Cursor::new(jpeg))?))
+    } else if header.get(4..12) == Some(b"ftypcrx ") {
+        // CMT1 holds IFD0: camera, orientation and the capture time
+        let Some(moov) = read_top_level_box(path, b"moov") else {
+            return Ok(None);
+        };
+        let cmt1 = Boxes::new(&moov)
+            .filter(|(t, body)| t == b"uuid" && body.starts_with(&CANON_UUID))
+            .find_map(|(_, body)| find_box(&body[CANON_UUID.len()..], &[b"CMT1"]));
+        cmt1.map(|tiff| Reader::new().read_raw(tiff.to_vec())).transpose().map_err(Into::into)
+    } else {
+        Ok(None)
+    }
+}
+
+/// The start of the file with its magic number swapped for the standard TIFF one
+fn read_head(file: &mut fs::File, magic: [u8; 4]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.take(RAW_HEADER_LEN).read_to_end(&mut data)?;
+    data[..4].copy_from_slice(&magic);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exif_data::test_support::tiff_with_fields;
+    use exif::{Field, In, Tag, Value};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_exif_from_orf() {
+        let model = Field { tag: Tag::Model, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"E-M1".to_vec()]) };
+        let mut orf = tiff_with_fields(&[model]);
+        orf[..4].copy_from_slice(b"IIRO");
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("P1010001.ORF");
+        fs::write(&path, orf).unwrap();
+        let exif = read_exif(&path).unwrap().unwrap();
+        assert_eq!(exif.get_field(Tag::Model, In::PRIMARY).unwrap().display_value().to_string(), "\"E-M1\"");
+
+        fs::write(&path, b"not a raw file").unwrap();
+        assert!(read_exif(&path).unwrap().is_none());
+    }
+}
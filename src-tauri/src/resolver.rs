@@ -0,0 +1,411 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use chrono::{DateTime, Utc};
+use crate::catalog::Catalog;
+use crate::error::{Error, Result};
+use crate::types::*;
+
+/// Name of the manifest written into each quarantine batch folder
+pub const MANIFEST_NAME: &str = "kosmos-manifest.json";
+
+/// Remove every non-keeper in `groups` using `method`. The journal is saved to the
+/// catalog after each group, so even an interrupted resolution can be undone.
+pub fn resolve_duplicates(
+    catalog: &mut Catalog,
+    groups: &[DuplicateGroup],
+    method: ResolutionMethod,
+    quarantine_dir: Option<&str>,
+) -> Result<Resolution> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let resolved_at = Utc::now();
+    let id = format!("{}-{}", resolved_at.format("%Y%m%d-%H%M%S-%3f"), COUNTER.fetch_add(1, Ordering::Relaxed));
+
+    let batch_dir = match (method, quarantine_dir) {
+        (ResolutionMethod::Quarantine, Some(dir)) => Some(Path::new(dir).join(&id)),
+        (ResolutionMethod::Quarantine, None) => return Err(Error::Invalid("A quarantine folder is required".to_string())),
+        _ => None,
+    };
+
+    let mut resolution = Resolution {
+        id,
+        method,
+        resolved_at,
+        quarantine_dir: batch_dir.as_ref().map(|d| d.to_string_lossy().to_string()),
+        files: Vec::new(),
+        reclaimed_bytes: 0,
+        undone_at: None,
+    };
+    let mut saved_entries: Vec<FileMetadata> = Vec::new();
+
+    for group in groups {
+        let mut removed: Vec<&str> = Vec::new();
+
+        for file in group.files.iter().filter(|f| f.path != group.keeper) {
+            let path = Path::new(&file.path);
+            let mut entry = ResolvedFile {
+                path: file.path.clone(),
+                keeper: group.keeper.clone(),
+                file_size: file.file_size,
+                stored_path: None,
+                status: OperationStatus::Success,
+                error_message: None,
+                restored: false,
+            };
+
+            let result = match skip_reason(catalog, group, file, method) {
+                Ok(Some(reason)) => {
+                    entry.status = OperationStatus::Skipped;
+                    entry.error_message = Some(reason);
+                    Ok(())
+                }
+                Ok(None) => match method {
+                    ResolutionMethod::Trash => trash::delete(path).map_err(Error::from),
+                    ResolutionMethod::Quarantine => {
+                        let stored = quarantine_path(batch_dir.as_deref().unwrap(), path);
+                        entry.stored_path = Some(stored.to_string_lossy().to_string());
+                        move_file(path, &stored).map_err(Error::from)
+                    }
+                    ResolutionMethod::Hardlink if matches!(group.kind, DuplicateKind::Exact) => {
+                        replace_with_hardlink(Path::new(&group.keeper), path)
+                    }
+                    ResolutionMethod::Hardlink => {
+                        Err(Error::Invalid("Only exact copies can be hardlinked".to_string()))
+                    }
+                },
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(()) if matches!(entry.status, OperationStatus::Success) => {
+                    resolution.reclaimed_bytes += file.file_size;
+                    saved_entries.push(file.clone());
+                    if !matches!(method, ResolutionMethod::Hardlink) {
+                        removed.push(&file.path);
+                    }
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    entry.status = OperationStatus::Failed;
+                    entry.error_message = Some(e.to_string());
+                }
+            }
+
+            resolution.files.push(entry);
+        }
+
+        catalog.remove_files(&removed)?;
+        save_journal(catalog, &resolution, &saved_entries)?;
+    }
+
+    save_journal(catalog, &resolution, &saved_entries)?;
+    Ok(resolution)
+}
+
+/// Why a non-keeper must be left alone, if it must. Groups come from the
+/// frontend, so only what the catalog and the disk still agree on is acted on.
+fn skip_reason(catalog: &Catalog, group: &DuplicateGroup, file: &FileMetadata, method: ResolutionMethod) -> Result<Option<String>> {
+    let (path, keeper) = (Path::new(&file.path), Path::new(&group.keeper));
+    if !path.exists() {
+        return Ok(Some("File does not exist".to_string()));
+    }
+    // Never remove the last copy
+    if !keeper.exists() {
+        return Ok(Some("Keeper does not exist".to_string()));
+    }
+    for (role, path) in [("File", path), ("Keeper", keeper)] {
+        let Some(cataloged) = catalog.get_file(&path.to_string_lossy())? else {
+            return Ok(Some(format!("{role} is not in the catalog")));
+        };
+        let metadata = fs::metadata(path)?;
+        let modified_at = metadata.modified().ok().map(DateTime::<Utc>::from);
+        if cataloged.file_size != metadata.len() || cataloged.modified_at != modified_at {
+            return Ok(Some(format!("{role} changed since it was scanned")));
+        }
+    }
+    // Hardlinking compares contents itself, right before linking
+    if matches!(group.kind, DuplicateKind::Exact) && !matches!(method, ResolutionMethod::Hardlink) && !same_contents(keeper, path)? {
+        return Ok(Some("File no longer matches the keeper".to_string()));
+    }
+    Ok(None)
+}
+
+/// Put back every file a resolution removed and restore its catalog entry.
+/// Files that can't be restored keep their journal entry, so undo can be retried.
+pub fn undo_resolution(catalog: &mut Catalog, id: &str) -> Result<Resolution> {
+    let (mut resolution, saved_entries) = catalog.get_resolution(id)?
+        .ok_or_else(|| Error::ResolutionNotFound(id.to_string()))?;
+
+    if resolution.undone_at.is_some() {
+        return Err(Error::Invalid("This resolution has already been undone".to_string()));
+    }
+
+    let method = resolution.method;
+    let mut restored_entries: Vec<FileMetadata> = Vec::new();
+
+    for entry in resolution.files.iter_mut() {
+        if entry.restored || !matches!(entry.status, OperationStatus::Success) {
+            continue;
+        }
+
+        let saved = saved_entries.iter().find(|f| f.path == entry.path);
+        let path = Path::new(&entry.path);
+        let result = match method {
+            ResolutionMethod::Trash => restore_from_trash(path),
+            ResolutionMethod::Quarantine => match &entry.stored_path {
+                Some(_) if path.exists() => Err(Error::Invalid("Something else now exists at this path".to_string())),
+                Some(stored) => move_file(Path::new(stored), path).map_err(Error::from),
+                None => Err(Error::Invalid("Quarantine location was not recorded".to_string())),
+            },
+            ResolutionMethod::Hardlink => break_hardlink(path, saved),
+        };
+
+        match result {
+            Ok(()) => {
+                entry.restored = true;
+                entry.error_message = None;
+                if let Some(saved) = saved {
+                    restored_entries.push(saved.clone());
+                }
+            }
+            Err(e) => entry.error_message = Some(e.to_string()),
+        }
+    }
+
+    let all_restored = resolution.files.iter()
+        .all(|f| f.restored || !matches!(f.status, OperationStatus::Success));
+    if all_restored {
+        resolution.undone_at = Some(Utc::now());
+    }
+
+    catalog.upsert_files(&restored_entries)?;
+    catalog.record_resolution(&resolution, &saved_entries)?;
+    if let Some(dir) = &resolution.quarantine_dir {
+        write_manifest(Path::new(dir), &resolution)?;
+    }
+
+    Ok(resolution)
+}
+
+fn save_journal(catalog: &mut Catalog, resolution: &Resolution, saved_entries: &[FileMetadata]) -> Result<()> {
+    catalog.record_resolution(resolution, saved_entries)?;
+    match &resolution.quarantine_dir {
+        Some(dir) if !resolution.files.is_empty() => write_manifest(Path::new(dir), resolution),
+        _ => Ok(()),
+    }
+}
+
+fn write_manifest(batch_dir: &Path, resolution: &Resolution) -> Result<()> {
+    let json = serde_json::to_string_pretty(resolution)?;
+    fs::create_dir_all(batch_dir)?;
+    fs::write(batch_dir.join(MANIFEST_NAME), json)?;
+    Ok(())
+}
+
+/// Mirror the file's absolute path inside the batch folder, so names never collide
+fn quarantine_path(batch_dir: &Path, path: &Path) -> PathBuf {
+    let mut stored = batch_dir.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => stored.push(part),
+            Component::Prefix(prefix) => {
+                // Windows drive letters: "C:" becomes "C"
+                stored.push(prefix.as_os_str().to_string_lossy().replace([':', '\\', '?'], ""))
+            }
+            _ => {}
+        }
+    }
+    stored
+}
+
+/// Rename, falling back to copy + delete across filesystems
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+fn replace_with_hardlink(keeper: &Path, duplicate: &Path) -> Result<()> {
+    // The catalog hash may be stale; never link files that differ now
+    if !same_contents(keeper, duplicate)? {
+        return Err(Error::Invalid("File no longer matches the keeper".to_string()));
+    }
+
+    // Link next to the duplicate, then rename over it so it is never missing
+    let temp = sibling_temp_path(duplicate);
+    fs::hard_link(keeper, &temp)?;
+    fs::rename(&temp, duplicate).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        e.into()
+    })
+}
+
+/// Give a hardlinked path its own copy of the data again
+fn break_hardlink(path: &Path, saved: Option<&FileMetadata>) -> Result<()> {
+    let temp = sibling_temp_path(path);
+    fs::copy(path, &temp)?;
+
+    if let Some(modified) = saved.and_then(|f| f.modified_at) {
+        let _ = fs::File::options()
+            .write(true)
+            .open(&temp)
+            .and_then(|f| f.set_modified(modified.into()));
+    }
+
+    fs::rename(&temp, path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        e.into()
+    })
+}
+
+fn sibling_temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".kosmos-tmp");
+    path.with_file_name(name)
+}
+
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    let (mut a, mut b) = (fs::File::open(a)?, fs::File::open(b)?);
+    let (mut buf_a, mut buf_b) = ([0u8; 8192], [0u8; 8192]);
+    loop {
+        let count = a.read(&mut buf_a)?;
+        if count == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..count])?;
+        if buf_a[..count] != buf_b[..count] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(any(windows, all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))))]
+fn restore_from_trash(path: &Path) -> Result<()> {
+    use trash::os_limited;
+
+    let item = os_limited::list()?
+        .into_iter()
+        .filter(|item| item.original_path() == path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| Error::Invalid("File is no longer in the trash".to_string()))?;
+
+    Ok(os_limited::restore_all([item])?)
+}
+
+#[cfg(not(any(windows, all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android")))))]
+fn restore_from_trash(_path: &Path) -> Result<()> {
+    Err(Error::Invalid("Restoring from the trash isn't supported on this platform; use \"Put Back\" in the Trash".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn make_group(dir: &Path, kind: DuplicateKind) -> DuplicateGroup {
+        let file = |name: &str| {
+            let path = dir.join(name);
+            fs::write(&path, b"same bytes").unwrap();
+            let mut file = FileMetadata::for_test(&path.to_string_lossy(), "h", 10, FileType::Image);
+            file.modified_at = fs::metadata(&path).unwrap().modified().ok().map(DateTime::<Utc>::from);
+            file
+        };
+        let files = vec![file("keeper.jpg"), file("copy.jpg")];
+        DuplicateGroup { kind, keeper: files[0].path.clone(), files }
+    }
+
+    #[test]
+    fn test_quarantine_and_undo() {
+        let dir = TempDir::new().unwrap();
+        let photos = dir.path().join("photos");
+        fs::create_dir(&photos).unwrap();
+        let group = make_group(&photos, DuplicateKind::Exact);
+        let copy = group.files[1].path.clone();
+
+        let mut catalog = Catalog::open_in_memory().unwrap();
+        catalog.upsert_files(&group.files).unwrap();
+
+        let quarantine = dir.path().join("quarantine");
+        let resolution = resolve_duplicates(
+            &mut catalog, &[group], ResolutionMethod::Quarantine, Some(&quarantine.to_string_lossy()),
+        ).unwrap();
+
+        assert_eq!(resolution.reclaimed_bytes, 10);
+        assert!(!Path::new(&copy).exists());
+        let stored = resolution.files[0].stored_path.clone().unwrap();
+        assert!(Path::new(&stored).exists());
+        assert!(Path::new(resolution.quarantine_dir.as_ref().unwrap()).join(MANIFEST_NAME).exists());
+        assert!(catalog.get_file(&copy).unwrap().is_none());
+
+        let undone = undo_resolution(&mut catalog, &resolution.id).unwrap();
+        assert!(undone.undone_at.is_some());
+        assert!(Path::new(&copy).exists());
+        assert!(!Path::new(&stored).exists());
+        assert!(catalog.get_file(&copy).unwrap().is_some());
+        assert!(undo_resolution(&mut catalog, &resolution.id).is_err());
+    }
+
+    #[test]
+    fn test_hardlink_and_undo() {
+        let dir = TempDir::new().unwrap();
+        let group = make_group(dir.path(), DuplicateKind::Exact);
+        let (keeper, copy) = (group.files[0].path.clone(), group.files[1].path.clone());
+        let mut catalog = Catalog::open_in_memory().unwrap();
+        catalog.upsert_files(&group.files).unwrap();
+
+        let resolution = resolve_duplicates(&mut catalog, &[group], ResolutionMethod::Hardlink, None).unwrap();
+        assert!(matches!(resolution.files[0].status, OperationStatus::Success));
+
+        // Writing through the keeper shows up in the linked copy
+        fs::write(&keeper, b"edited bytes").unwrap();
+        assert_eq!(fs::read(&copy).unwrap(), b"edited bytes");
+
+        undo_resolution(&mut catalog, &resolution.id).unwrap();
+        fs::write(&keeper, b"third").unwrap();
+        assert_eq!(fs::read(&copy).unwrap(), b"edited bytes");
+
+        // Similar (not byte-identical) groups are never linked
+        let other = dir.path().join("other");
+        fs::create_dir(&other).unwrap();
+        let similar = make_group(&other, DuplicateKind::Similar);
+        catalog.upsert_files(&similar.files).unwrap();
+        let resolution = resolve_duplicates(&mut catalog, &[similar], ResolutionMethod::Hardlink, None).unwrap();
+        assert!(matches!(resolution.files[0].status, OperationStatus::Failed));
+    }
+
+    #[test]
+    fn test_stale_groups_are_skipped() {
+        let dir = TempDir::new().unwrap();
+        let group = make_group(dir.path(), DuplicateKind::Exact);
+        let copy = group.files[1].path.clone();
+        let mut catalog = Catalog::open_in_memory().unwrap();
+        let quarantine = dir.path().join("quarantine").to_string_lossy().to_string();
+        let quarantine_group = |catalog: &mut Catalog| {
+            let resolution = resolve_duplicates(catalog, std::slice::from_ref(&group), ResolutionMethod::Quarantine, Some(&quarantine)).unwrap();
+            resolution.files[0].error_message.clone()
+        };
+
+        assert_eq!(quarantine_group(&mut catalog).as_deref(), Some("File is not in the catalog"));
+
+        // Same size and mtime, different bytes
+        catalog.upsert_files(&group.files).unwrap();
+        let modified = fs::metadata(&copy).unwrap().modified().unwrap();
+        fs::write(&copy, b"other byte").unwrap();
+        fs::File::options().write(true).open(&copy).unwrap().set_modified(modified).unwrap();
+        assert_eq!(quarantine_group(&mut catalog).as_deref(), Some("File no longer matches the keeper"));
+
+        fs::write(&copy, b"grown since the scan").unwrap();
+        assert_eq!(quarantine_group(&mut catalog).as_deref(), Some("File changed since it was scanned"));
+        assert!(Path::new(&copy).exists());
+    }
+}
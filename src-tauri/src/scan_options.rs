@@ -0,0 +1,194 @@
+use std::path::Path;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use serde::{Deserialize, Serialize};
+use walkdir::DirEntry;
+use crate::error::Result;
+use crate::types::HashMode;
+
+/// Ignore file, in gitignore syntax, read from the scan root
+pub const IGNORE_FILE_NAME: &str = ".kosmosignore";
+
+/// Folders that never hold photos worth organizing: VCS and build output,
+/// NAS and OS thumbnail caches, trash and system folders
+const JUNK_FOLDERS: &[&str] = &[
+    ".git", ".svn", ".hg", "node_modules", "__pycache__",
+    "@eaDir", "#recycle", "#snapshot",                      // Synology
+    ".thumbnails", ".thumbs", ".picasaoriginals", ".cache",
+    "__MACOSX", ".Trashes", ".Spotlight-V100", ".fseventsd", ".DocumentRevisions-V100",
+    "$RECYCLE.BIN", "System Volume Information",
+    ".Trash", ".Trash-1000",
+];
+
+/// Files that are OS or app bookkeeping, never user content
+const JUNK_FILES: &[&str] = &[".DS_Store", "Thumbs.db", "ehthumbs.db", "desktop.ini", ".picasa.ini", "Picasa.ini"];
+
+/// Everything the frontend can configure about a scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    pub incremental: bool,              // Reuse cataloged metadata for unchanged files
+    pub home_timezone: Option<String>,  // IANA name, e.g. "Europe/Berlin"; assumed for photos without an EXIF offset
+    pub home_utc_offset: Option<i32>,   // Minutes; used when home_timezone is not set
+    pub hash_mode: HashMode,
+    pub include: Vec<String>,           // Globs relative to the root; when set, other files are skipped
+    pub exclude: Vec<String>,           // Globs relative to the root, for files or folders
+    pub max_depth: Option<usize>,       // Folder levels below the root; 0 scans only the root itself
+    pub include_hidden: bool,           // Dotfiles and dot-folders
+    pub min_file_size: u64,             // Bytes
+    pub follow_symlinks: bool,
+    pub skip_junk: bool,                // Built-in JUNK_FOLDERS and JUNK_FILES
+    pub use_ignore_file: bool,          // Honour .kosmosignore in the root
+    pub scan_archives: bool,            // Catalog the photos inside ZIP and TAR(.gz) files instead of the files
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            incremental: false,
+            home_timezone: None,
+            home_utc_offset: None,
+            hash_mode: HashMode::default(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: None,
+            include_hidden: false,
+            min_file_size: 0,
+            follow_symlinks: false,
+            skip_junk: true,
+            use_ignore_file: true,
+            scan_archives: false,
+        }
+    }
+}
+
+/// Compiled scope rules for one scan root
+pub struct ScanFilter {
+    skip_junk: bool,
+    include_hidden: bool,
+    min_file_size: u64,
+    globs: Override,
+    ignore_file: Gitignore,
+}
+
+impl ScanFilter {
+    /// Compile the globs and read `.kosmosignore`. Fails on a malformed glob or ignore file.
+    pub fn new(root: &Path, options: &ScanOptions) -> Result<Self> {
+        // Overrides treat plain globs as a whitelist and `!glob` as an exclusion
+        let mut globs = OverrideBuilder::new(root);
+        for glob in &options.include {
+            globs.add(glob)?;
+        }
+        for glob in &options.exclude {
+            globs.add(&format!("!{glob}"))?;
+        }
+
+        let mut ignore_file = GitignoreBuilder::new(root);
+        let path = root.join(IGNORE_FILE_NAME);
+        if options.use_ignore_file && path.is_file() {
+            if let Some(e) = ignore_file.add(path) {
+                return Err(e.into());
+            }
+        }
+
+        Ok(Self {
+            skip_junk: options.skip_junk,
+            include_hidden: options.include_hidden,
+            min_file_size: options.min_file_size,
+            globs: globs.build()?,
+            ignore_file: ignore_file.build()?,
+        })
+    }
+
+    /// Whether to keep a walk entry. Rejected folders aren't descended into.
+    pub fn allows(&self, entry: &DirEntry) -> bool {
+        // Never filter out the root the user picked, even if it is hidden
+        if entry.depth() == 0 {
+            return true;
+        }
+
+        let name = entry.file_name().to_string_lossy();
+        let is_dir = entry.file_type().is_dir();
+
+        if self.skip_junk {
+            let junk = if is_dir { JUNK_FOLDERS } else { JUNK_FILES };
+            // "._name" files are macOS resource forks left behind on non-Apple disks
+            if junk.iter().any(|j| j.eq_ignore_ascii_case(&name)) || (!is_dir && name.starts_with("._")) {
+                return false;
+            }
+        }
+
+        if !self.include_hidden && name.starts_with('.') {
+            return false;
+        }
+
+        if self.globs.matched(entry.path(), is_dir).is_ignore()
+            || self.ignore_file.matched(entry.path(), is_dir).is_ignore()
+        {
+            return false;
+        }
+
+        is_dir || self.min_file_size == 0
+            || entry.metadata().is_ok_and(|m| m.len() >= self.min_file_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+    use walkdir::WalkDir;
+
+    #[test]
+    fn test_scan_filter() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        for path in [
+            "a.jpg", "tiny.jpg", "._a.jpg", ".DS_Store", ".hidden.jpg", "notes.txt",
+            "2023/b.jpg", "2023/raw/c.jpg", "2023/drafts/d.jpg",
+            "@eaDir/a.jpg/SYNOFILE_THUMB_M.jpg", "node_modules/pkg/logo.jpg", "private/e.jpg",
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, if path.ends_with("tiny.jpg") { vec![0; 10] } else { vec![0; 100] }).unwrap();
+        }
+        fs::write(root.join(IGNORE_FILE_NAME), "private/\n# comment\n*/drafts\n").unwrap();
+
+        let scan = |options: ScanOptions| -> Vec<String> {
+            let filter = ScanFilter::new(root, &options).unwrap();
+            let mut files: Vec<String> = WalkDir::new(root)
+                .max_depth(options.max_depth.map_or(usize::MAX, |d| d + 1))
+                .into_iter()
+                .filter_entry(|e| filter.allows(e))
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .map(|e| e.path().strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/"))
+                .collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(scan(ScanOptions::default()), vec![
+            "2023/b.jpg", "2023/raw/c.jpg", "a.jpg", "notes.txt", "tiny.jpg",
+        ]);
+
+        let narrowed = ScanOptions {
+            include: vec!["*.jpg".into()],
+            exclude: vec!["raw".into()],
+            min_file_size: 50,
+            include_hidden: true,
+            use_ignore_file: false,
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan(narrowed), vec![
+            ".hidden.jpg", "2023/b.jpg", "2023/drafts/d.jpg", "a.jpg", "private/e.jpg",
+        ]);
+
+        assert_eq!(scan(ScanOptions { max_depth: Some(0), ..ScanOptions::default() }), vec![
+            "a.jpg", "notes.txt", "tiny.jpg",
+        ]);
+
+        assert!(ScanFilter::new(root, &ScanOptions { include: vec!["[".into()], ..ScanOptions::default() }).is_err());
+    }
+}
@@ -1,209 +1,334 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::io;
 use walkdir::WalkDir;
-use sha2::{Sha256, Digest};
-use chrono::{DateTime, Utc, NaiveDateTime};
-use std::io::Read;
-use exif::{Reader, In, Tag};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rayon::prelude::*;
 use tauri::{AppHandle, Emitter};
 
+use crate::catalog::CatalogState;
+use crate::dates::{resolve_date, ResolvedDate};
+use crate::archives::{entry_path, for_each_file, is_archive, split_entry_path, staged_path};
+use crate::detect::{detect_file_type, is_media_extension, is_raw_extension};
+use crate::downloads::DownloadRules;
+use crate::error::{Error, Result};
+use crate::exif_data::{ExifData, HomeTimezone};
+use crate::hashing::{content_hash, hash_possible_duplicates};
+use crate::live_photos::{is_motion_photo, pair_live_photos};
+use crate::companions::link_companions;
+use crate::scan_options::{ScanFilter, ScanOptions};
+use crate::similarity;
+use crate::screenshots::{detect_screenshot, ScreenshotEvidence};
+use crate::quality::{self, BLUR_THRESHOLD};
+use crate::isobmff::{read_image_info, read_video_info, rotation_to_orientation};
+use crate::sessions::{next_session_id, ScanControl};
 use crate::types::*;
 
 pub struct Scanner {
     root_path: PathBuf,
+    options: ScanOptions,
+    home_timezone: HomeTimezone,
+    download_rules: DownloadRules,
+    control: Arc<ScanControl>,
 }
 
 impl Scanner {
     pub fn new(root_path: impl AsRef<Path>) -> Self {
         Self {
             root_path: root_path.as_ref().to_path_buf(),
+            options: ScanOptions::default(),
+            home_timezone: HomeTimezone::System,
+            download_rules: DownloadRules::default(),
+            control: Arc::new(ScanControl::new(next_session_id())),
         }
     }
 
-    pub fn scan(&self, app_handle: &AppHandle) -> Result<ScanResult, Box<dyn std::error::Error>> {
-        // Step 1: Quick count of total files
-        let total_files = self.count_total_files();
-        
-        let mut files = Vec::new();
-        let mut total_size = 0u64;
-        let mut screenshot_count = 0;
-        let mut image_count = 0;
-        let mut video_count = 0;
-        let mut processed_count = 0;
-
-        for entry in WalkDir::new(&self.root_path)
-            .follow_links(false)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry.file_type().is_file() {
-                let file_name = entry.path()
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_string();
-                
-                if let Some(ext) = entry.path().extension() {
-                    let ext = ext.to_string_lossy().to_lowercase();
-                    
-                    // Check if it's an image file
-                    if is_image_extension(&ext) {
-                        if let Ok(metadata) = self.process_image(entry.path()) {
-                            total_size += metadata.file_size;
-                            if metadata.is_screenshot {
-                                screenshot_count += 1;
+    /// Attach a session so the scan can be cancelled or paused from another command
+    pub fn with_control(mut self, control: Arc<ScanControl>) -> Self {
+        self.control = control;
+        self
+    }
+
+    /// Incremental mode, home timezone, hashing and which files are in scope.
+    /// Fails on an unknown home timezone name.
+    pub fn options(mut self, options: ScanOptions) -> Result<Self> {
+        self.home_timezone = HomeTimezone::from_settings(options.home_timezone.as_deref(), options.home_utc_offset)?;
+        self.options = options;
+        Ok(self)
+    }
+
+    /// Rules for flagging memes and messaging-app downloads
+    pub fn download_rules(mut self, download_rules: DownloadRules) -> Self {
+        self.download_rules = download_rules;
+        self
+    }
+
+    /// Run the scan. The catalog is locked only to read the previous scan and to
+    /// store the result, so other commands stay responsive during the walk.
+    pub fn scan(&self, app_handle: &AppHandle, catalog: &CatalogState) -> Result<ScanResult> {
+        let root_path = self.root_path.to_string_lossy().to_string();
+
+        // Previous scan of this root, keyed by path (incremental mode only)
+        let previous: HashMap<String, FileMetadata> = if self.options.incremental {
+            catalog.lock()?
+                .files_under(&root_path)?
+                .into_iter()
+                .map(|f| (f.path.clone(), f))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        // Cataloged archive entries, by archive
+        let mut archived: HashMap<&Path, Vec<&FileMetadata>> = HashMap::new();
+        for file in previous.values() {
+            if let Some((archive, _)) = split_entry_path(&file.path) {
+                archived.entry(archive).or_default().push(file);
+            }
+        }
+
+        // Stage 1: walk the tree, skipping junk and anything out of scope
+        let filter = ScanFilter::new(&self.root_path, &self.options)?;
+        let (candidates, mut errors) = self.collect_candidates(&filter);
+        let total_files = candidates.len();
+        let processed_count = AtomicUsize::new(0);
+
+        // Stage 2: classify, hash and extract metadata in parallel.
+        // Indexed collect keeps results in walk order, so output is deterministic.
+        let processed: Vec<(Vec<FileMetadata>, Vec<ScanError>)> = candidates
+            .par_iter()
+            .map(|entry| {
+                // Stop picking up new files once cancelled (blocks while paused)
+                if !self.control.checkpoint() {
+                    return (Vec::new(), Vec::new());
+                }
+
+                let mut entry_errors = Vec::new();
+                let metadata = if self.options.scan_archives && is_archive(entry.path()) {
+                    unchanged_archive(&archived, entry)
+                        .unwrap_or_else(|| self.process_archive(entry.path(), &mut entry_errors))
+                } else {
+                    match unchanged_entry(&previous, entry) {
+                        Some(mut cached) => {
+                            // A prefilter scan may have cataloged it without the hash this mode promises
+                            if cached.hash.is_none() && matches!(self.options.hash_mode, HashMode::Full) {
+                                let hash = self.hash(entry.path());
+                                cached.hash = report(&mut entry_errors, entry.path(), ScanStage::Hash, hash).flatten();
                             }
-                            image_count += 1;
-                            files.push(metadata);
-                        }
-                    }
-                    // Check if it's a video file
-                    else if is_video_extension(&ext) {
-                        if let Ok(metadata) = self.process_video(entry.path()) {
-                            total_size += metadata.file_size;
-                            video_count += 1;
-                            files.push(metadata);
+                            vec![cached]
                         }
+                        None => self.process_entry(entry.path(), &mut entry_errors).into_iter().collect(),
                     }
-                }
-                
+                };
+
                 // Update progress
-                processed_count += 1;
-                
+                let processed = processed_count.fetch_add(1, Ordering::Relaxed) + 1;
+
                 // Emit progress every 10 files or on last file
-                if processed_count % 10 == 0 || processed_count == total_files {
-                    let percentage = if total_files > 0 {
-                        (processed_count as f32 / total_files as f32) * 100.0
-                    } else {
-                        0.0
-                    };
-                    
+                if processed.is_multiple_of(10) || processed == total_files {
+                    let percentage = (processed as f32 / total_files as f32) * 100.0;
+
                     let progress = ScanProgress {
+                        session_id: self.control.id().to_string(),
                         total_files,
-                        processed_files: processed_count,
-                        current_file: file_name,
+                        processed_files: processed,
+                        current_file: entry.file_name().to_string_lossy().to_string(),
                         percentage,
                     };
-                    
+
                     // Emit progress event (ignore errors)
                     let _ = app_handle.emit("scan-progress", &progress);
                 }
-            }
-        }
-
-        // Calculate date range
-        let date_range = files.iter()
-            .filter_map(|f| f.date_taken.or(f.modified_at))
-            .fold(None, |acc, date| {
-                match acc {
-                    None => Some((date, date)),
-                    Some((min, max)) => Some((min.min(date), max.max(date)))
-                }
-            });
 
-        // Calculate quality issues
-        let image_files: Vec<&FileMetadata> = files.iter()
-            .filter(|f| matches!(f.file_type, FileType::Image))
+                (metadata, entry_errors)
+            })
             .collect();
 
-        // 1. Count screenshots (already detected)
-        let screenshots = screenshot_count;
+        let mut files = Vec::with_capacity(processed.len());
+        for (metadata, entry_errors) in processed {
+            files.extend(metadata);
+            errors.extend(entry_errors);
+        }
 
-        // 2. Detect duplicates by hash
-        let mut hash_map: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
-        for file in &files {
-            *hash_map.entry(file.hash.clone()).or_insert(0) += 1;
+        // Stage 3: in prefilter mode, fully hash only files that share a size and partial hash
+        if matches!(self.options.hash_mode, HashMode::Prefilter) {
+            errors.extend(hash_possible_duplicates(&mut files, &self.control));
         }
-        let duplicates = hash_map.values().filter(|&&count| count > 1)
-            .map(|&count| count - 1)
-            .sum();
-
-        // 3. Low resolution images (below 1920×1080)
-        let low_resolution = image_files.iter()
-            .filter(|f| {
-                if let (Some(w), Some(h)) = (f.width, f.height) {
-                    (w as u64) * (h as u64) < (1920 * 1080)
-                } else {
-                    false
-                }
-            })
-            .count();
-
-        // 4. Small files (compressed/low quality - below 500KB)
-        let small_files = image_files.iter()
-            .filter(|f| f.file_size < 500 * 1024)
-            .count();
-
-        // 5. Missing metadata (no EXIF date)
-        let missing_metadata = image_files.iter()
-            .filter(|f| f.date_taken.is_none())
-            .count();
-
-        // 6. Potential memes/downloads (suspicious filenames)
-        let suspicious_patterns = [
-            "meme", "funny", "lol", "image", "download", "untitled",
-            "img_", "pic_", "photo_", "picture_", "file_", "temp"
-        ];
-        let potential_memes = files.iter()
-            .filter(|f| {
-                let file_name = f.file_name.to_lowercase();
-                suspicious_patterns.iter().any(|pattern| file_name.contains(pattern))
-            })
-            .count();
-
-        let stats = ScanStats {
-            total_files: files.len(),
-            file_types: FileTypeStats {
-                images: image_count,
-                videos: video_count,
-                documents: 0,
-                audio: 0,
-                archives: 0,
-                other: 0,
-            },
-            screenshots: screenshot_count,
-            duplicates,
-            total_size,
-            date_range,
-            quality: QualityIssues {
-                screenshots,
-                duplicates,
-                low_resolution,
-                small_files,
-                missing_metadata,
-                potential_memes,
-            },
+
+        // Stage 4: link Live Photo stills with their clips, and RAW+JPEG pairs and
+        // sidecars into shots that move as one
+        pair_live_photos(&mut files);
+        link_companions(&mut files, candidates.iter().map(|e| e.path()));
+
+        let complete = !self.control.is_cancelled();
+        let stats = compute_stats(&files);
+
+        // Persist to the catalog so later commands don't need the file list over IPC.
+        // A cancelled scan only adds what it saw; it must not drop unvisited files.
+        let mut catalog = catalog.lock()?;
+        let changes = if complete {
+            catalog.replace_root(&root_path, &files)?;
+            self.options.incremental.then(|| diff_scans(&previous, &files))
+        } else {
+            catalog.upsert_files(&files)?;
+            None
         };
 
         Ok(ScanResult { 
-            root_path: self.root_path.to_string_lossy().to_string(),
+            session_id: self.control.id().to_string(),
+            root_path,
             files, 
-            stats 
+            stats,
+            changes,
+            complete,
+            errors,
         })
     }
     
-    // Quick count of total files (just metadata, no processing)
-    fn count_total_files(&self) -> usize {
-        WalkDir::new(&self.root_path)
-            .follow_links(false)
+    // Walk the tree (sorted, so order is stable between runs) and list every file,
+    // along with the folders and entries that couldn't be read
+    fn collect_candidates(&self, filter: &ScanFilter) -> (Vec<walkdir::DirEntry>, Vec<ScanError>) {
+        let mut errors = Vec::new();
+        let entries = WalkDir::new(&self.root_path)
+            .follow_links(self.options.follow_symlinks)
+            .max_depth(self.options.max_depth.map_or(usize::MAX, |depth| depth + 1))
+            .sort_by_file_name()
             .into_iter()
-            .filter_map(|e| e.ok())
+            .filter_entry(|e| filter.allows(e))
+            .filter_map(|e| {
+                let path = e.as_ref().err().and_then(|e| e.path()).map(Path::to_path_buf);
+                report(&mut errors, &path.unwrap_or_else(|| self.root_path.clone()), ScanStage::Walk, e)
+            })
             .filter(|e| e.file_type().is_file())
-            .count()
+            .collect();
+        (entries, errors)
+    }
+
+    // Classify by content and route to the matching processor; None for unrecognised
+    // or unreadable files. Problems along the way are added to `errors`.
+    fn process_entry(&self, path: &Path, errors: &mut Vec<ScanError>) -> Option<FileMetadata> {
+        let detection = detect_file_type(path)?;
+
+        let mut metadata = match &detection.file_type {
+            FileType::Image => self.process_image(path, detection.format, errors),
+            FileType::Video => self.process_video(path, errors),
+            other => self.process_file(path, other.clone(), errors),
+        }?;
+
+        metadata.detected_format = detection.format.map(str::to_string);
+        metadata.extension_mismatch = detection.extension_mismatch;
+        self.download_rules.classify(&mut metadata);
+        Some(metadata)
     }
 
-    fn process_image(&self, path: &Path) -> Result<FileMetadata, Box<dyn std::error::Error>> {
-        let metadata = fs::metadata(path)?;
+    // Unpack the photos and videos in a ZIP or TAR one at a time and catalog them
+    // under virtual paths ("backup.zip!/DCIM/IMG_1.jpg"). Entries are staged with
+    // their folders and mtime, so the date fallbacks still have something to go on.
+    fn process_archive(&self, archive: &Path, errors: &mut Vec<ScanError>) -> Vec<FileMetadata> {
+        let Some(stamp) = report(errors, archive, ScanStage::Metadata, fs::metadata(archive)).map(|m| archive_stamp(&m)) else {
+            return Vec::new();
+        };
+        let Some(staging) = report(errors, archive, ScanStage::Archive, tempfile::tempdir()) else {
+            return Vec::new();
+        };
+        let mut files = Vec::new();
+
+        // Takeout JSON and XMP sidecars stay staged for the whole archive, since
+        // a TAR may store them before or after their photo
+        let sidecars = for_each_file(archive, |name, modified, reader| {
+            let sidecar = Path::new(name).extension()
+                .is_some_and(|e| matches!(e.to_string_lossy().to_lowercase().as_str(), "json" | "xmp"));
+            if let Some(staged) = staged_path(staging.path(), name).filter(|_| sidecar) {
+                stage(&staged, modified, reader)?;
+            }
+            Ok(self.control.checkpoint())
+        });
+        if report(errors, archive, ScanStage::Archive, sidecars).is_none() {
+            return files;
+        }
+
+        let result = for_each_file(archive, |name, modified, reader| {
+            let media = Path::new(name).extension()
+                .is_some_and(|e| is_media_extension(&e.to_string_lossy().to_lowercase()));
+            let Some(staged) = staged_path(staging.path(), name).filter(|_| media) else {
+                return Ok(true);
+            };
+            if !self.control.checkpoint() {
+                return Ok(false);
+            }
+            stage(&staged, modified, reader)?;
+
+            let virtual_path = entry_path(archive, name);
+            let mut entry_errors = Vec::new();
+            if let Some(mut metadata) = self.process_entry(&staged, &mut entry_errors) {
+                // The staged copy is gone by the time prefilter hashing runs
+                if metadata.hash.is_none() {
+                    metadata.hash = report(&mut entry_errors, &staged, ScanStage::Hash, content_hash(&staged));
+                }
+                metadata.path = virtual_path.clone();
+                metadata.archive = Some(stamp.clone());
+                // The staged copy was only just created
+                metadata.created_at = None;
+                files.push(metadata);
+            }
+            errors.extend(entry_errors.into_iter().map(|e| ScanError { path: virtual_path.clone(), ..e }));
+            // Leftovers go with the staging directory
+            report(errors, Path::new(&virtual_path), ScanStage::Archive, fs::remove_file(&staged));
+            Ok(true)
+        });
+
+        report(errors, archive, ScanStage::Archive, result);
+        files
+    }
+
+    fn process_image(&self, path: &Path, format: Option<&str>, errors: &mut Vec<ScanError>) -> Option<FileMetadata> {
+        let metadata = report(errors, path, ScanStage::Metadata, fs::metadata(path))?;
         let file_size = metadata.len();
         
-        // Calculate file hash
-        let hash = self.calculate_hash(path)?;
+        // Calculate file hash (deferred to stage 3 in prefilter mode)
+        let hash = report(errors, path, ScanStage::Hash, self.hash(path))?;
         
+        // The decoder would only find a RAW's embedded preview, so RAW files
+        // get their size from EXIF and skip decoding altogether
+        let raw = path.extension()
+            .is_some_and(|e| is_raw_extension(&e.to_string_lossy().to_lowercase()));
+
         // Try to read image dimensions
-        let (width, height) = match image::image_dimensions(path) {
-            Ok((w, h)) => (Some(w), Some(h)),
-            Err(_) => (None, None),
+        // (guess the format from content, since the extension may be wrong).
+        // Failures show up again when decoding, so they're reported there.
+        let dimensions = (!raw).then(|| {
+            image::ImageReader::open(path)
+                .and_then(|reader| reader.with_guessed_format())
+                .map_err(image::ImageError::IoError)
+                .and_then(|reader| reader.into_dimensions())
+        });
+        let (mut width, mut height) = match dimensions {
+            Some(Ok((w, h))) => (Some(w), Some(h)),
+            _ => (None, None),
+        };
+
+        // HEIF and AVIF have no decoder here; their container states size and rotation
+        let heif = matches!(format, Some("heif" | "avif")).then(|| read_image_info(path)).flatten();
+        if let Some(info) = heif.as_ref().filter(|_| width.is_none()) {
+            (width, height) = (info.width, info.height);
+        }
+
+        // Decode once for the perceptual hash (near-duplicates) and quality scoring.
+        // Formats the decoder doesn't support (HEIC, RAW) aren't worth reporting.
+        let image = if raw {
+            None
+        } else {
+            match quality::decode_image(path) {
+                Err(e) if matches!(e.kind(), ScanErrorKind::Unsupported) => None,
+                result => report(errors, path, ScanStage::Decode, result),
+            }
         };
+        let perceptual_hash = image.as_ref().map(|i| similarity::format_hash(similarity::dhash(i)));
+        let image_quality = image.as_ref().map(quality::analyze);
+        drop(image);
 
         // Extract dates
         let created_at = metadata.created().ok()
@@ -211,41 +336,99 @@ impl Scanner {
         let modified_at = metadata.modified().ok()
             .map(DateTime::<Utc>::from);
 
-        // Extract EXIF data for actual date_taken
-        let date_taken = self.extract_exif_date(path).or(modified_at);
+        // Extract EXIF data for actual date_taken and camera settings
+        let exif = report(errors, path, ScanStage::Exif, ExifData::read(path)).flatten();
+        if width.is_none() {
+            (width, height) = exif.as_ref().and_then(|e| e.dimensions()).unzip();
+        }
+        let exif_date = exif.as_ref()
+            .and_then(|e| e.date_taken(&self.home_timezone))
+            .map(|d| ResolvedDate::local(d, DateSource::Exif));
+
+        // Without EXIF, try sidecars, the file name and its folders before settling for the mtime
+        let resolved = resolve_date(path, exif_date, &self.home_timezone);
+        let date_taken = resolved.map(|d| d.date).or(modified_at);
+        let date_taken_offset = resolved.and_then(|d| d.offset);
+        let date_source = resolved.map(|d| d.source)
+            .or(modified_at.map(|_| DateSource::Modified));
+        let mut camera = exif.as_ref().map(|e| e.camera()).unwrap_or_default();
+        // In HEIF the container's rotation is the one viewers apply
+        if let Some(info) = heif.as_ref().filter(|i| i.rotation != 0) {
+            camera.orientation = Some(rotation_to_orientation(info.rotation));
+        }
+        let gps = exif.as_ref().and_then(|e| e.gps());
 
-        // Detect if it's likely a screenshot
-        let is_screenshot = self.is_likely_screenshot(path, width, height);
+        // Weigh screenshot signals: tool tags, file name, screen resolution, PNG without camera
+        let file_name = path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let software = exif.as_ref().and_then(|e| e.software());
+        let user_comment = exif.as_ref().and_then(|e| e.user_comment());
+        let screenshot = detect_screenshot(&ScreenshotEvidence {
+            file_name: &file_name,
+            format,
+            width,
+            height,
+            has_camera_exif: camera.make.is_some() || camera.model.is_some(),
+            software: software.as_deref(),
+            user_comment: user_comment.as_deref(),
+        });
 
-        Ok(FileMetadata {
+        Some(FileMetadata {
             path: path.to_string_lossy().to_string(),
-            file_name: path.file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string(),
+            file_name,
             file_size,
             file_type: FileType::Image,
             created_at,
             modified_at,
             date_taken,
+            date_taken_offset,
+            date_source,
             width,
             height,
             duration: None,
             page_count: None,
             hash,
-            is_screenshot,
+            perceptual_hash,
+            sharpness: image_quality.map(|q| q.sharpness),
+            exposure: image_quality.map(|q| q.exposure),
+            quality_score: image_quality.map(|q| q.score),
+            is_screenshot: screenshot.is_screenshot(),
+            screenshot_confidence: Some(screenshot.confidence),
+            screenshot_reasons: screenshot.reasons,
+            is_likely_download: false,
+            download_rules: Vec::new(),
             is_duplicate: false,
-            camera_make: None,
-            camera_model: None,
+            content_identifier: exif.as_ref().and_then(|e| e.content_identifier()),
+            paired_with: None,
+            motion_photo: format == Some("jpeg") && is_motion_photo(path),
+            companions: Vec::new(),
+            companion_of: None,
+            archive: None,
+            camera_make: camera.make,
+            camera_model: camera.model,
+            lens_model: camera.lens_model,
+            focal_length: camera.focal_length,
+            iso: camera.iso,
+            aperture: camera.aperture,
+            exposure_time: camera.exposure_time,
+            flash_fired: camera.flash_fired,
+            orientation: camera.orientation,
+            latitude: gps.map(|g| g.latitude),
+            longitude: gps.map(|g| g.longitude),
+            altitude: gps.and_then(|g| g.altitude),
+            detected_format: None,
+            extension_mismatch: false,
         })
     }
 
-    fn process_video(&self, path: &Path) -> Result<FileMetadata, Box<dyn std::error::Error>> {
-        let metadata = fs::metadata(path)?;
+    fn process_video(&self, path: &Path, errors: &mut Vec<ScanError>) -> Option<FileMetadata> {
+        let metadata = report(errors, path, ScanStage::Metadata, fs::metadata(path))?;
         let file_size = metadata.len();
         
-        // Calculate file hash
-        let hash = self.calculate_hash(path)?;
+        // Calculate file hash (deferred to stage 3 in prefilter mode)
+        let hash = report(errors, path, ScanStage::Hash, self.hash(path))?;
 
         // Extract dates
         let created_at = metadata.created().ok()
@@ -253,7 +436,17 @@ impl Scanner {
         let modified_at = metadata.modified().ok()
             .map(DateTime::<Utc>::from);
 
-        Ok(FileMetadata {
+        // Read the container's own metadata (MP4/MOV/3GP)
+        let info = read_video_info(path).unwrap_or_default();
+
+        // mvhd times are plain UTC, so they go on the home clock; only QuickTime
+        // creationdate says where the clip was shot
+        let container_date = info.created_local
+            .or_else(|| info.created.map(|utc| self.home_timezone.at(utc)))
+            .map(|d| ResolvedDate::local(d, DateSource::Container));
+        let resolved = resolve_date(path, container_date, &self.home_timezone);
+
+        Some(FileMetadata {
             path: path.to_string_lossy().to_string(),
             file_name: path.file_name()
                 .unwrap_or_default()
@@ -263,134 +456,523 @@ impl Scanner {
             file_type: FileType::Video,
             created_at,
             modified_at,
+            date_taken: resolved.map(|d| d.date),
+            date_taken_offset: resolved.and_then(|d| d.offset),
+            date_source: resolved.map(|d| d.source),
+            width: info.width,
+            height: info.height,
+            duration: info.duration,
+            page_count: None,
+            hash,
+            perceptual_hash: None,
+            sharpness: None,
+            exposure: None,
+            quality_score: None,
+            is_screenshot: false,
+            screenshot_confidence: None,
+            screenshot_reasons: Vec::new(),
+            is_likely_download: false,
+            download_rules: Vec::new(),
+            is_duplicate: false,
+            content_identifier: info.content_identifier,
+            paired_with: None,
+            motion_photo: false,
+            companions: Vec::new(),
+            companion_of: None,
+            archive: None,
+            camera_make: None,
+            camera_model: None,
+            lens_model: None,
+            focal_length: None,
+            iso: None,
+            aperture: None,
+            exposure_time: None,
+            flash_fired: None,
+            orientation: (info.rotation != 0).then(|| rotation_to_orientation(info.rotation)),
+            latitude: info.location.map(|g| g.latitude),
+            longitude: info.location.map(|g| g.longitude),
+            altitude: info.location.and_then(|g| g.altitude),
+            detected_format: None,
+            extension_mismatch: false,
+        })
+    }
+
+    // Documents, audio and archives: hash and filesystem dates only
+    fn process_file(&self, path: &Path, file_type: FileType, errors: &mut Vec<ScanError>) -> Option<FileMetadata> {
+        let metadata = report(errors, path, ScanStage::Metadata, fs::metadata(path))?;
+        let file_size = metadata.len();
+
+        // Calculate file hash (deferred to stage 3 in prefilter mode)
+        let hash = report(errors, path, ScanStage::Hash, self.hash(path))?;
+
+        // Extract dates
+        let created_at = metadata.created().ok()
+            .map(DateTime::<Utc>::from);
+        let modified_at = metadata.modified().ok()
+            .map(DateTime::<Utc>::from);
+
+        Some(FileMetadata {
+            path: path.to_string_lossy().to_string(),
+            file_name: path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            file_size,
+            file_type,
+            created_at,
+            modified_at,
             date_taken: None,
-            width: None,  // Could extract with ffmpeg later
+            date_taken_offset: None,
+            date_source: None,
+            width: None,
             height: None,
-            duration: None,  // Could extract with ffmpeg later
+            duration: None,
             page_count: None,
             hash,
+            perceptual_hash: None,
+            sharpness: None,
+            exposure: None,
+            quality_score: None,
             is_screenshot: false,
+            screenshot_confidence: None,
+            screenshot_reasons: Vec::new(),
+            is_likely_download: false,
+            download_rules: Vec::new(),
             is_duplicate: false,
+            content_identifier: None,
+            paired_with: None,
+            motion_photo: false,
+            companions: Vec::new(),
+            companion_of: None,
+            archive: None,
             camera_make: None,
             camera_model: None,
+            lens_model: None,
+            focal_length: None,
+            iso: None,
+            aperture: None,
+            exposure_time: None,
+            flash_fired: None,
+            orientation: None,
+            latitude: None,
+            longitude: None,
+            altitude: None,
+            detected_format: None,
+            extension_mismatch: false,
         })
     }
 
-    fn calculate_hash(&self, path: &Path) -> Result<String, Box<dyn std::error::Error>> {
-        let mut file = fs::File::open(path)?;
-        let mut hasher = Sha256::new();
-        let mut buffer = [0; 8192];
+    fn hash(&self, path: &Path) -> Result<Option<String>> {
+        match self.options.hash_mode {
+            HashMode::Full => Ok(Some(content_hash(path)?)),
+            HashMode::Prefilter => Ok(None),
+        }
+    }
+}
 
-        loop {
-            let count = file.read(&mut buffer)?;
-            if count == 0 {
-                break;
-            }
-            hasher.update(&buffer[..count]);
+/// Record a failed step for the scan report and carry on without its result
+fn report<T, E: Into<Error>>(errors: &mut Vec<ScanError>, path: &Path, stage: ScanStage, result: Result<T, E>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            errors.push(ScanError::new(path, stage, &e.into()));
+            None
         }
+    }
+}
 
-        Ok(format!("{:x}", hasher.finalize()))
+/// Copy an archive entry to `staged`, keeping its modification time
+fn stage(staged: &Path, modified: Option<DateTime<Utc>>, reader: &mut dyn io::Read) -> io::Result<()> {
+    if let Some(parent) = staged.parent() {
+        fs::create_dir_all(parent)?;
     }
+    let mut file = fs::File::create(staged)?;
+    io::copy(reader, &mut file)?;
+    if let Some(modified) = modified {
+        file.set_modified(modified.into())?;
+    }
+    Ok(())
+}
 
-    fn is_likely_screenshot(&self, path: &Path, width: Option<u32>, height: Option<u32>) -> bool {
-        // Check filename patterns
-        let file_name = path.file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_lowercase();
+/// Cataloged metadata for a file whose size and mtime match the previous scan
+fn unchanged_entry(previous: &HashMap<String, FileMetadata>, entry: &walkdir::DirEntry) -> Option<FileMetadata> {
+    let cached = previous.get(entry.path().to_string_lossy().as_ref())?;
+    let metadata = entry.metadata().ok()?;
+    let modified_at = metadata.modified().ok().map(DateTime::<Utc>::from);
 
-        if file_name.contains("screenshot") || 
-           file_name.contains("screen shot") ||
-           file_name.starts_with("screen_") {
-            return true;
-        }
+    if cached.file_size == metadata.len() && cached.modified_at == modified_at {
+        Some(cached.clone())
+    } else {
+        None
+    }
+}
 
-        // Check common screenshot dimensions
-        if let (Some(w), Some(h)) = (width, height) {
-            // Common mobile screenshot dimensions
-            let aspect_ratio = w as f64 / h as f64;
-            let is_mobile_aspect = (aspect_ratio - 9.0/16.0).abs() < 0.1 || 
-                                  (aspect_ratio - 16.0/9.0).abs() < 0.1;
-            
-            // Common screenshot sizes
-            let is_common_screenshot_size = 
-                (w == 1920 && h == 1080) ||
-                (w == 1080 && h == 1920) ||
-                (w == 1080 && h == 2340) ||
-                (w == 1080 && h == 2400) ||
-                (w == 1284 && h == 2778);
-
-            if is_mobile_aspect && is_common_screenshot_size {
-                return true;
-            }
-        }
+/// Cataloged entries of an archive whose size and mtime match the previous scan
+fn unchanged_archive(archived: &HashMap<&Path, Vec<&FileMetadata>>, entry: &walkdir::DirEntry) -> Option<Vec<FileMetadata>> {
+    let cached = archived.get(entry.path())?;
+    let stamp = archive_stamp(&entry.metadata().ok()?);
 
-        false
+    if cached.iter().all(|f| f.archive.as_ref() == Some(&stamp)) {
+        let mut files: Vec<FileMetadata> = cached.iter().map(|&f| f.clone()).collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Some(files)
+    } else {
+        None
     }
+}
 
-    fn extract_exif_date(&self, path: &Path) -> Option<DateTime<Utc>> {
-        let file = fs::File::open(path).ok()?;
-        let mut bufreader = std::io::BufReader::new(&file);
-        let exifreader = Reader::new();
-        let exif = exifreader.read_from_container(&mut bufreader).ok()?;
+fn archive_stamp(metadata: &fs::Metadata) -> ArchiveStamp {
+    ArchiveStamp {
+        file_size: metadata.len(),
+        modified_at: metadata.modified().ok().map(DateTime::<Utc>::from),
+    }
+}
 
-        // Try DateTimeOriginal first (when photo was taken)
-        if let Some(field) = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY) {
-            if let Some(datetime_str) = field.display_value().to_string().split('"').nth(1) {
-                return Self::parse_exif_datetime(datetime_str);
+/// Compare a fresh scan against the previous one for the same root.
+/// A removed path whose hash reappears at an added path counts as a move;
+/// files that were never fully hashed are matched on size, name and mtime instead.
+fn diff_scans(previous: &HashMap<String, FileMetadata>, files: &[FileMetadata]) -> ScanChanges {
+    let current: HashMap<&str, &FileMetadata> = files.iter()
+        .map(|f| (f.path.as_str(), f))
+        .collect();
+
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for file in files {
+        match previous.get(&file.path) {
+            None => added.push(file),
+            Some(old) if old.file_size != file.file_size || old.modified_at != file.modified_at => {
+                changed.push(file.path.clone());
             }
+            Some(_) => {}
         }
+    }
 
-        // Fall back to DateTime (when file was created)
-        if let Some(field) = exif.get_field(Tag::DateTime, In::PRIMARY) {
-            if let Some(datetime_str) = field.display_value().to_string().split('"').nth(1) {
-                return Self::parse_exif_datetime(datetime_str);
+    let mut removed: Vec<&FileMetadata> = previous.values()
+        .filter(|f| !current.contains_key(f.path.as_str()))
+        .collect();
+    removed.sort_by(|a, b| a.path.cmp(&b.path));
+
+    // Pair removed files with added ones by content hash
+    let mut removed_by_hash: HashMap<&str, Vec<&FileMetadata>> = HashMap::new();
+    let mut removed_by_identity: HashMap<FileIdentity, Vec<&FileMetadata>> = HashMap::new();
+    for file in removed.iter().rev() {
+        if let Some(hash) = &file.hash {
+            removed_by_hash.entry(hash.as_str()).or_default().push(file);
+        }
+        removed_by_identity.entry(identity(file)).or_default().push(file);
+    }
+
+    let mut moved = Vec::new();
+    let mut moved_from: HashSet<&str> = HashSet::new();
+    let mut added_paths = Vec::new();
+    for file in added {
+        let by_hash = file.hash.as_deref().and_then(|hash| removed_by_hash.get_mut(hash));
+        let by_identity = removed_by_identity.get_mut(&identity(file));
+        let old = by_hash.and_then(|c| take_unmoved(c, &moved_from))
+            .or_else(|| by_identity.and_then(|c| take_unmoved(c, &moved_from)));
+
+        match old {
+            Some(old) => {
+                moved_from.insert(old.path.as_str());
+                moved.push(FileMove {
+                    from: old.path.clone(),
+                    to: file.path.clone(),
+                });
             }
+            None => added_paths.push(file.path.clone()),
         }
+    }
 
-        None
+    let removed = removed.into_iter()
+        .filter(|f| !moved_from.contains(f.path.as_str()))
+        .map(|f| f.path.clone())
+        .collect();
+
+    ScanChanges {
+        added: added_paths,
+        changed,
+        removed,
+        moved,
     }
+}
 
-    fn parse_exif_datetime(datetime_str: &str) -> Option<DateTime<Utc>> {
-        // EXIF datetime format: "YYYY:MM:DD HH:MM:SS"
-        let parts: Vec<&str> = datetime_str.split(' ').collect();
-        if parts.len() != 2 {
-            return None;
-        }
+/// Size, name and mtime: what a move keeps when there is no content hash to compare
+type FileIdentity<'a> = (u64, &'a str, Option<DateTime<Utc>>);
+
+fn identity(file: &FileMetadata) -> FileIdentity<'_> {
+    (file.file_size, file.file_name.as_str(), file.modified_at)
+}
 
-        let date_parts: Vec<&str> = parts[0].split(':').collect();
-        let time_parts: Vec<&str> = parts[1].split(':').collect();
+fn take_unmoved<'a>(candidates: &mut Vec<&'a FileMetadata>, moved_from: &HashSet<&str>) -> Option<&'a FileMetadata> {
+    while let Some(file) = candidates.pop() {
+        if !moved_from.contains(file.path.as_str()) {
+            return Some(file);
+        }
+    }
+    None
+}
 
-        if date_parts.len() != 3 || time_parts.len() != 3 {
-            return None;
+/// Summarize a set of scanned files (type counts, quality issues, date range)
+pub fn compute_stats(files: &[FileMetadata]) -> ScanStats {
+    let mut file_types = FileTypeStats {
+        images: 0,
+        videos: 0,
+        documents: 0,
+        audio: 0,
+        archives: 0,
+        other: 0,
+    };
+    for file in files {
+        match file.file_type {
+            FileType::Image => file_types.images += 1,
+            FileType::Video => file_types.videos += 1,
+            FileType::Document => file_types.documents += 1,
+            FileType::Audio => file_types.audio += 1,
+            FileType::Archive => file_types.archives += 1,
+            FileType::Other => file_types.other += 1,
         }
+    }
 
-        let year = date_parts[0].parse::<i32>().ok()?;
-        let month = date_parts[1].parse::<u32>().ok()?;
-        let day = date_parts[2].parse::<u32>().ok()?;
-        let hour = time_parts[0].parse::<u32>().ok()?;
-        let minute = time_parts[1].parse::<u32>().ok()?;
-        let second = time_parts[2].parse::<u32>().ok()?;
+    let total_size = files.iter().map(|f| f.file_size).sum();
 
-        NaiveDateTime::parse_from_str(
-            &format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02}"),
-            "%Y-%m-%d %H:%M:%S"
-        )
-        .ok()
-        .map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc))
+    // Calculate date range
+    let date_range = files.iter()
+        .filter_map(|f| f.date_taken.or(f.modified_at))
+        .fold(None, |acc, date| {
+            match acc {
+                None => Some((date, date)),
+                Some((min, max)) => Some((min.min(date), max.max(date)))
+            }
+        });
+
+    // Calculate quality issues
+    let image_files: Vec<&FileMetadata> = files.iter()
+        .filter(|f| matches!(f.file_type, FileType::Image))
+        .collect();
+
+    // 1. Count screenshots (already detected)
+    let screenshots = files.iter().filter(|f| f.is_screenshot).count();
+
+    // 2. Detect duplicates by hash (files without one have no possible copy)
+    let mut hash_map: HashMap<&str, usize> = HashMap::new();
+    for hash in files.iter().filter_map(|f| f.hash.as_deref()) {
+        *hash_map.entry(hash).or_insert(0) += 1;
+    }
+    let duplicates = hash_map.values().filter(|&&count| count > 1)
+        .map(|&count| count - 1)
+        .sum();
+
+    // 3. Low resolution images (below 1920×1080)
+    let low_resolution = image_files.iter()
+        .filter(|f| {
+            if let (Some(w), Some(h)) = (f.width, f.height) {
+                (w as u64) * (h as u64) < (1920 * 1080)
+            } else {
+                false
+            }
+        })
+        .count();
+
+    // 4. Small files (compressed/low quality - below 500KB)
+    let small_files = image_files.iter()
+        .filter(|f| f.file_size < 500 * 1024)
+        .count();
+
+    // 5. Missing metadata (no EXIF date)
+    let missing_metadata = image_files.iter()
+        .filter(|f| f.date_taken.is_none())
+        .count();
+
+    // 6. Content doesn't match the file extension
+    let extension_mismatches = files.iter()
+        .filter(|f| f.extension_mismatch)
+        .count();
+
+    // 7. Potential memes/downloads (tagged by the download rules)
+    let potential_memes = files.iter()
+        .filter(|f| f.is_likely_download)
+        .count();
+
+    // 8. Blurry and badly exposed photos (from the quality pass)
+    let blurry = image_files.iter()
+        .filter(|f| f.sharpness.is_some_and(|s| s < BLUR_THRESHOLD))
+        .count();
+    let badly_exposed = image_files.iter()
+        .filter(|f| matches!(f.exposure, Some(Exposure::Under | Exposure::Over)))
+        .count();
+
+    // Images with and without GPS coordinates
+    let with_location = image_files.iter()
+        .filter(|f| f.latitude.is_some() && f.longitude.is_some())
+        .count();
+
+    ScanStats {
+        total_files: files.len(),
+        file_types,
+        screenshots,
+        duplicates,
+        total_size,
+        date_range,
+        quality: QualityIssues {
+            screenshots,
+            duplicates,
+            low_resolution,
+            small_files,
+            missing_metadata,
+            potential_memes,
+            extension_mismatches,
+            blurry,
+            badly_exposed,
+        },
+        location: LocationStats {
+            with_location,
+            without_location: image_files.len() - with_location,
+        },
     }
 }
 
-fn is_image_extension(ext: &str) -> bool {
-    matches!(
-        ext,
-        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "heic" | "heif" | "tiff" | "tif"
-    )
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_scans() {
+        let previous: HashMap<String, FileMetadata> = [
+            FileMetadata::for_test("/p/same.jpg", "s", 10, FileType::Image),
+            FileMetadata::for_test("/p/edited.jpg", "e1", 10, FileType::Image),
+            FileMetadata::for_test("/p/old/moved.jpg", "m", 10, FileType::Image),
+            FileMetadata::for_test("/p/gone.jpg", "g", 10, FileType::Image),
+            FileMetadata { hash: None, ..FileMetadata::for_test("/p/old/unhashed.jpg", "", 20, FileType::Image) },
+        ]
+        .into_iter()
+        .map(|f| (f.path.clone(), f))
+        .collect();
+
+        let files = vec![
+            FileMetadata::for_test("/p/same.jpg", "s", 10, FileType::Image),
+            FileMetadata::for_test("/p/edited.jpg", "e2", 12, FileType::Image),
+            FileMetadata::for_test("/p/new/moved.jpg", "m", 10, FileType::Image),
+            FileMetadata::for_test("/p/new.jpg", "n", 10, FileType::Image),
+            FileMetadata { hash: None, ..FileMetadata::for_test("/p/new/unhashed.jpg", "", 20, FileType::Image) },
+        ];
+
+        let changes = diff_scans(&previous, &files);
+        assert_eq!(changes.added, vec!["/p/new.jpg"]);
+        assert_eq!(changes.changed, vec!["/p/edited.jpg"]);
+        assert_eq!(changes.removed, vec!["/p/gone.jpg"]);
+        assert_eq!(changes.moved.len(), 2);
+        assert_eq!(changes.moved[0].from, "/p/old/moved.jpg");
+        assert_eq!(changes.moved[0].to, "/p/new/moved.jpg");
+        assert_eq!(changes.moved[1].from, "/p/old/unhashed.jpg");
+    }
+
+    #[test]
+    fn test_compute_stats_counts_every_file_type() {
+        let files = vec![
+            FileMetadata::for_test("/p/a.jpg", "a", 10, FileType::Image),
+            FileMetadata::for_test("/p/b.pdf", "b", 10, FileType::Document),
+            FileMetadata::for_test("/p/c.flac", "c", 10, FileType::Audio),
+            FileMetadata::for_test("/p/d.zip", "d", 10, FileType::Archive),
+            FileMetadata::for_test("/p/e.zip", "d", 10, FileType::Archive),
+        ];
+
+        let stats = compute_stats(&files);
+        assert_eq!(stats.file_types.images, 1);
+        assert_eq!(stats.file_types.documents, 1);
+        assert_eq!(stats.file_types.audio, 1);
+        assert_eq!(stats.file_types.archives, 2);
+        assert_eq!(stats.duplicates, 1);
+        assert_eq!(stats.total_size, 50);
+    }
+
+    #[test]
+    fn test_mvhd_dates_go_on_the_home_clock() {
+        use crate::isobmff::test_support::{make_box, mvhd};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("clip.mp4");
+        let mut file = make_box(b"ftyp", b"isom\0\0\0\0");
+        file.extend(make_box(b"moov", &mvhd(1_564_615_800, 600, 600)));
+        fs::write(&path, file).unwrap();
+
+        let scanner = Scanner::new(dir.path()).options(ScanOptions { home_utc_offset: Some(120), ..Default::default() }).unwrap();
+        let clip = scanner.process_video(&path, &mut Vec::new()).unwrap();
+        assert_eq!(clip.date_taken.unwrap().to_rfc3339(), "2019-07-31T23:30:00+00:00");
+        assert_eq!(clip.date_taken_offset, Some(120));
+        assert_eq!(clip.date_source, Some(DateSource::Container));
+    }
+
+    #[test]
+    fn test_unreadable_image_is_reported_not_dropped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("broken.jpg");
+        // A JPEG header with nothing after it
+        fs::write(&path, [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00]).unwrap();
+
+        let mut errors = Vec::new();
+        let metadata = Scanner::new(dir.path()).process_entry(&path, &mut errors);
 
-fn is_video_extension(ext: &str) -> bool {
-    matches!(
-        ext,
-        "mp4" | "mov" | "avi" | "mkv" | "m4v" | "wmv" | "flv" | "webm" | "3gp"
-    )
+        assert!(metadata.is_some_and(|m| m.width.is_none()));
+        assert!(errors.iter().any(|e| matches!(e.stage, ScanStage::Decode) && matches!(e.kind, ScanErrorKind::Corrupt)));
+        assert!(errors.iter().all(|e| e.path == path.to_string_lossy()));
+    }
+
+    #[test]
+    fn test_archive_entries_get_virtual_paths() {
+        use sha2::Digest;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut png = Vec::new();
+        image::RgbImage::new(4, 3)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let archive = dir.path().join("backup.zip");
+        crate::archives::test_support::write_zip(&archive, &[
+            ("DCIM/IMG_20190703_142233.png", &png),
+            ("notes.txt", b"not media"),
+        ]);
+
+        let mut errors = Vec::new();
+        let files = Scanner::new(dir.path()).process_archive(&archive, &mut errors);
+
+        assert!(errors.is_empty());
+        assert_eq!(files.len(), 1);
+        let photo = &files[0];
+        assert_eq!(photo.path, entry_path(&archive, "DCIM/IMG_20190703_142233.png"));
+        assert_eq!(photo.file_name, "IMG_20190703_142233.png");
+        assert_eq!((photo.width, photo.height), (Some(4), Some(3)));
+        assert_eq!(photo.hash, Some(format!("{:x}", sha2::Sha256::digest(&png))));
+        assert_eq!(photo.date_source, Some(DateSource::FileName));
+        assert_eq!(photo.created_at, None);
+
+        // A rescan reuses the entries until the archive changes
+        let entry = || WalkDir::new(&archive).into_iter().next().unwrap().unwrap();
+        let archived = HashMap::from([(archive.as_path(), files.iter().collect())]);
+        assert_eq!(unchanged_archive(&archived, &entry()).map(|f| f.len()), Some(1));
+        crate::archives::test_support::write_zip(&archive, &[("DCIM/IMG_20190703_142233.png", &png)]);
+        assert!(unchanged_archive(&archived, &entry()).is_none());
+    }
+
+    #[test]
+    fn test_archive_entries_find_their_takeout_json() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut jpeg = Vec::new();
+        image::RgbImage::new(4, 3)
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let archive = dir.path().join("takeout.zip");
+        crate::archives::test_support::write_zip(&archive, &[
+            ("Takeout/IMG.jpg", &jpeg),
+            ("Takeout/IMG.jpg.json", br#"{"photoTakenTime": {"timestamp": "1562163753"}}"#),
+        ]);
+
+        let mut errors = Vec::new();
+        let files = Scanner::new(dir.path()).process_archive(&archive, &mut errors);
+
+        assert!(errors.is_empty());
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].date_source, Some(DateSource::Takeout));
+        assert_eq!(files[0].date_taken.map(|d| d.timestamp()), Some(1562163753));
+    }
 }
@@ -0,0 +1,195 @@
+/// Confidence at which a file is flagged as a screenshot
+pub const SCREENSHOT_THRESHOLD: f32 = 0.5;
+
+/// Native screen resolutions (portrait, width × height) of common phones,
+/// tablets and monitors. Landscape captures match with the sides swapped.
+const DEVICE_RESOLUTIONS: &[(u32, u32, &str)] = &[
+    // iPhone
+    (640, 1136, "iPhone SE (1st gen)"),
+    (750, 1334, "iPhone 6-8 / SE"),
+    (1242, 2208, "iPhone Plus"),
+    (1125, 2436, "iPhone X / XS / 11 Pro"),
+    (828, 1792, "iPhone XR / 11"),
+    (1242, 2688, "iPhone XS Max / 11 Pro Max"),
+    (1080, 2340, "iPhone 12/13 mini"),
+    (1170, 2532, "iPhone 12-14"),
+    (1284, 2778, "iPhone 12-14 Pro Max / Plus"),
+    (1179, 2556, "iPhone 14 Pro / 15 / 16"),
+    (1290, 2796, "iPhone 14 Pro Max / 15 Plus / 16 Plus"),
+    (1206, 2622, "iPhone 16 Pro"),
+    (1320, 2868, "iPhone 16 Pro Max"),
+    // Android
+    (720, 1280, "Android HD"),
+    (720, 1600, "Android HD+"),
+    (1080, 1920, "Android Full HD / iPhone Plus (scaled)"),
+    (1080, 2160, "Android 18:9"),
+    (1080, 2220, "Samsung Galaxy S8/S9"),
+    (1080, 2280, "Android 19:9"),
+    (1080, 2400, "Android 20:9"),
+    (1080, 2408, "Android 20:9"),
+    (1440, 2560, "Android QHD"),
+    (1440, 2960, "Samsung Galaxy S8+/S9+"),
+    (1440, 3040, "Samsung Galaxy S10"),
+    (1440, 3088, "Samsung Galaxy S20-S22 Ultra"),
+    (1440, 3120, "Samsung Galaxy S23/S24 Ultra"),
+    (1440, 3200, "Android QHD+"),
+    (1344, 2992, "Google Pixel 8 Pro / 9 Pro XL"),
+    (1280, 2856, "Google Pixel 9 Pro"),
+    // iPad
+    (768, 1024, "iPad (original)"),
+    (1536, 2048, "iPad Retina"),
+    (1620, 2160, "iPad 10.2\""),
+    (1668, 2224, "iPad Pro 10.5\""),
+    (1668, 2388, "iPad Pro 11\""),
+    (1640, 2360, "iPad Air / iPad 10th gen"),
+    (2048, 2732, "iPad Pro 12.9\""),
+    // Monitors and laptops (stored landscape-first, as they are usually held)
+    (1366, 768, "Laptop HD"),
+    (1440, 900, "MacBook Air 13\""),
+    (1920, 1080, "Full HD monitor"),
+    (1920, 1200, "WUXGA monitor"),
+    (2560, 1440, "QHD monitor"),
+    (2560, 1600, "MacBook Pro 13\" / Air"),
+    (2880, 1800, "MacBook Pro 15\""),
+    (3024, 1964, "MacBook Pro 14\""),
+    (3456, 2234, "MacBook Pro 16\""),
+    (3840, 2160, "4K monitor"),
+    (5120, 2880, "5K iMac / Studio Display"),
+];
+
+/// Default file names of screenshot tools, lowercased, across OS languages
+const FILENAME_PATTERNS: &[&str] = &[
+    "screenshot", "screen shot", "screen_shot", "screencapture", "screen capture",
+    "bildschirmfoto",           // German
+    "capture d’écran", "capture d'écran", "capture d'ecran", // French
+    "captura de pantalla",      // Spanish
+    "captura de tela", "captura de ecrã", // Portuguese
+    "schermafbeelding",         // Dutch
+    "istantanea", "schermata",  // Italian
+    "skärmavbild", "skärmbild", // Swedish
+    "skjermbilde",              // Norwegian
+    "skærmbillede",             // Danish
+    "näyttökuva",               // Finnish
+    "zrzut ekranu",             // Polish
+    "snímek obrazovky",         // Czech
+    "képernyőkép",              // Hungarian
+    "ekran görüntüsü", "ekran resmi", // Turkish
+    "снимок экрана", "скриншот", // Russian
+    "στιγμιότυπο οθόνης",       // Greek
+    "スクリーンショット",          // Japanese
+    "스크린샷",                   // Korean
+    "屏幕截图", "截屏", "截图", "螢幕擷取畫面", "螢幕截圖", // Chinese
+    "ภาพหน้าจอ",                  // Thai
+    "tangkapan layar",          // Indonesian
+];
+
+/// What the scanner knows about an image, as far as screenshots are concerned
+pub struct ScreenshotEvidence<'a> {
+    pub file_name: &'a str,
+    pub format: Option<&'a str>,        // Sniffed from content, not the extension
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub has_camera_exif: bool,          // Make or Model present
+    pub software: Option<&'a str>,
+    pub user_comment: Option<&'a str>,
+}
+
+/// Detector verdict: a confidence (0-1) and the signals behind it
+#[derive(Debug, Clone, Default)]
+pub struct ScreenshotVerdict {
+    pub confidence: f32,
+    pub reasons: Vec<String>,
+}
+
+impl ScreenshotVerdict {
+    pub fn is_screenshot(&self) -> bool {
+        self.confidence >= SCREENSHOT_THRESHOLD
+    }
+}
+
+/// Weigh every signal. Independent signals combine as 1 - Π(1 - weight),
+/// so two weak hints together outweigh either alone.
+pub fn detect_screenshot(evidence: &ScreenshotEvidence) -> ScreenshotVerdict {
+    let mut signals: Vec<(f32, String)> = Vec::new();
+
+    let tagged = [("Software", evidence.software), ("UserComment", evidence.user_comment)]
+        .into_iter()
+        .find_map(|(tag, value)| Some((tag, value.filter(|v| v.to_lowercase().contains("screenshot"))?)));
+    if let Some((tag, value)) = tagged {
+        signals.push((0.95, format!("EXIF {tag} is \"{value}\"")));
+    }
+
+    let name = evidence.file_name.to_lowercase();
+    if let Some(pattern) = FILENAME_PATTERNS.iter().find(|p| name.contains(*p)) {
+        signals.push((0.8, format!("File name contains \"{pattern}\"")));
+    }
+
+    if let Some(device) = evidence.width.zip(evidence.height).and_then(|(w, h)| device_for(w, h)) {
+        signals.push((0.35, format!("Exact screen resolution of {device}")));
+    }
+
+    if evidence.format == Some("png") && !evidence.has_camera_exif {
+        signals.push((0.4, "PNG without camera EXIF".to_string()));
+    }
+
+    let mut confidence = 1.0 - signals.iter().map(|(weight, _)| 1.0 - weight).product::<f32>();
+    let mut reasons: Vec<String> = signals.into_iter().map(|(_, reason)| reason).collect();
+
+    // A camera make/model is strong evidence of a real photo, unless a tool said otherwise
+    if evidence.has_camera_exif && tagged.is_none() && confidence > 0.0 {
+        confidence *= 0.25;
+        reasons.push("Has camera EXIF (counts against)".to_string());
+    }
+
+    ScreenshotVerdict { confidence, reasons }
+}
+
+fn device_for(width: u32, height: u32) -> Option<&'static str> {
+    DEVICE_RESOLUTIONS.iter()
+        .find(|&&(w, h, _)| (w, h) == (width, height) || (h, w) == (width, height))
+        .map(|&(_, _, device)| device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evidence<'a>(file_name: &'a str, format: &'a str, size: (u32, u32), has_camera_exif: bool) -> ScreenshotEvidence<'a> {
+        ScreenshotEvidence {
+            file_name,
+            format: Some(format),
+            width: Some(size.0),
+            height: Some(size.1),
+            has_camera_exif,
+            software: None,
+            user_comment: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_screenshot() {
+        // Android screenshot: PNG at a phone resolution
+        let v = detect_screenshot(&evidence("Screenshot_20230714-183000.png", "png", (1080, 2400), false));
+        assert!(v.is_screenshot());
+        assert_eq!(v.reasons.len(), 3);
+
+        // German macOS screenshot, landscape
+        assert!(detect_screenshot(&evidence("Bildschirmfoto 2023-07-14 um 18.30.00.png", "png", (2880, 1800), false)).is_screenshot());
+
+        // Unnamed PNG at an iPhone resolution
+        assert!(detect_screenshot(&evidence("IMG_0042.PNG", "png", (1179, 2556), false)).is_screenshot());
+
+        // iOS tags its screenshots even when renamed to JPEG
+        let mut tagged = evidence("IMG_0043.jpg", "jpeg", (4032, 3024), false);
+        tagged.user_comment = Some("Screenshot");
+        assert!(detect_screenshot(&tagged).is_screenshot());
+
+        // A 1080p camera frame is not a screenshot
+        let v = detect_screenshot(&evidence("DSC_0001.jpg", "jpeg", (1920, 1080), true));
+        assert!(!v.is_screenshot());
+        assert!(v.confidence > 0.0);
+
+        // Nor is a camera photo with an unusual size
+        assert_eq!(detect_screenshot(&evidence("IMG_1234.jpg", "jpeg", (4032, 3024), true)).confidence, 0.0);
+    }
+}
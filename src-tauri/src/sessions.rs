@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use chrono::Utc;
+
+/// Cancel/pause flags for one running scan, checked by the Scanner between files
+pub struct ScanControl {
+    id: String,
+    cancelled: AtomicBool,
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+impl ScanControl {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            cancelled: AtomicBool::new(false),
+            paused: Mutex::new(false),
+            resumed: Condvar::new(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn cancel(&self) {
+        // Hold the lock so a worker can't miss the wakeup between its check and wait
+        let _paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.resumed.notify_all();
+    }
+
+    pub fn pause(&self) {
+        *self.paused.lock().unwrap_or_else(|e| e.into_inner()) = true;
+    }
+
+    pub fn resume(&self) {
+        *self.paused.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        self.resumed.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Block while the scan is paused. Returns false once it has been cancelled.
+    pub fn checkpoint(&self) -> bool {
+        let mut paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+        while *paused && !self.is_cancelled() {
+            paused = self.resumed.wait(paused).unwrap_or_else(|e| e.into_inner());
+        }
+        !self.is_cancelled()
+    }
+}
+
+/// Generate a unique scan session ID
+pub fn next_session_id() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("scan-{}-{n}", Utc::now().timestamp_millis())
+}
+
+/// Registry of running scans, managed by Tauri
+#[derive(Default)]
+pub struct ScanSessions {
+    sessions: Mutex<HashMap<String, Arc<ScanControl>>>,
+}
+
+impl ScanSessions {
+    /// Register a new scan, generating an ID if the frontend didn't supply one
+    pub fn start(&self, id: Option<String>) -> Arc<ScanControl> {
+        let control = Arc::new(ScanControl::new(id.unwrap_or_else(next_session_id)));
+        self.lock().insert(control.id().to_string(), control.clone());
+        control
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<ScanControl>> {
+        self.lock().get(id).cloned()
+    }
+
+    pub fn finish(&self, id: &str) {
+        self.lock().remove(id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<ScanControl>>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cancel_wakes_paused_scan() {
+        let control = Arc::new(ScanControl::new("test"));
+        control.pause();
+
+        let worker = {
+            let control = control.clone();
+            thread::spawn(move || control.checkpoint())
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        control.cancel();
+        assert!(!worker.join().unwrap());
+    }
+}
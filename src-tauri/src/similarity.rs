@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use crate::types::*;
+
+/// Default Hamming distance (out of 64 bits) for two photos to count as near-duplicates
+pub const DEFAULT_MAX_DISTANCE: u32 = 8;
+
+/// 64-bit difference hash: shrink to 9×8 greyscale and compare each pixel with its right neighbour.
+/// Robust to resizing and re-compression, which is what messaging apps do to photos.
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+/// Hashes are stored as 16 hex digits: a u64 doesn't survive a trip through JavaScript numbers
+pub fn format_hash(hash: u64) -> String {
+    format!("{hash:016x}")
+}
+
+pub fn parse_hash(hash: &str) -> Option<u64> {
+    u64::from_str_radix(hash, 16).ok()
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Group images whose perceptual hashes are within `max_distance` of each other.
+/// Grouping is transitive, so A~B and B~C put A, B and C in one group.
+pub fn group_similar(files: &[FileMetadata], max_distance: u32) -> Vec<SimilarGroup> {
+    let hashed: Vec<(usize, u64)> = files.iter()
+        .enumerate()
+        .filter_map(|(i, f)| Some((i, parse_hash(f.perceptual_hash.as_deref()?)?)))
+        .collect();
+
+    let mut tree = BkTree::default();
+    for &(index, hash) in &hashed {
+        tree.insert(hash, index);
+    }
+
+    let mut sets = UnionFind::new(files.len());
+    let mut max_seen: HashMap<(usize, usize), u32> = HashMap::new();
+    for &(index, hash) in &hashed {
+        for (other, distance) in tree.find_within(hash, max_distance) {
+            if other != index {
+                sets.union(index, other);
+                max_seen.insert((index.min(other), index.max(other)), distance);
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &(index, _) in &hashed {
+        groups.entry(sets.find(index)).or_default().push(index);
+    }
+
+    let mut result: Vec<SimilarGroup> = groups.into_values()
+        .filter(|members| members.len() > 1)
+        .map(|mut members| {
+            members.sort_unstable();
+            let max_distance = max_seen.iter()
+                .filter(|((a, _), _)| members.binary_search(a).is_ok())
+                .map(|(_, &d)| d)
+                .max()
+                .unwrap_or(0);
+            SimilarGroup {
+                files: members.into_iter().map(|i| files[i].clone()).collect(),
+                max_distance,
+            }
+        })
+        .collect();
+
+    result.sort_by(|a, b| a.files[0].path.cmp(&b.files[0].path));
+    result
+}
+
+/// BK-tree over Hamming distance, so lookups don't compare every pair of photos
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    hash: u64,
+    index: usize,
+    children: HashMap<u32, usize>,
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, index: usize) {
+        let new_node = BkNode { hash, index, children: HashMap::new() };
+        if self.nodes.is_empty() {
+            self.nodes.push(new_node);
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let distance = hamming_distance(self.nodes[current].hash, hash);
+            match self.nodes[current].children.get(&distance) {
+                Some(&child) => current = child,
+                None => {
+                    let id = self.nodes.len();
+                    self.nodes.push(new_node);
+                    self.nodes[current].children.insert(distance, id);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn find_within(&self, hash: u64, max_distance: u32) -> Vec<(usize, u32)> {
+        let mut found = Vec::new();
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            let distance = hamming_distance(node.hash, hash);
+            if distance <= max_distance {
+                found.push((node.index, distance));
+            }
+
+            // Triangle inequality: only children in [d - max, d + max] can match
+            let low = distance.saturating_sub(max_distance);
+            let high = distance + max_distance;
+            stack.extend(node.children.iter()
+                .filter(|(&d, _)| d >= low && d <= high)
+                .map(|(_, &child)| child));
+        }
+
+        found
+    }
+}
+
+/// Disjoint sets of indices, merged in near-constant time
+pub struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    pub fn new(len: usize) -> Self {
+        Self { parent: (0..len).collect() }
+    }
+
+    pub fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        // Path compression
+        let mut i = i;
+        while self.parent[i] != root {
+            let next = self.parent[i];
+            self.parent[i] = root;
+            i = next;
+        }
+        root
+    }
+
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+
+    fn gradient(width: u32, height: u32, flip: bool) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+            let v = ((x * 255 / width) ^ (y * 64 / height)) as u8;
+            let v = if flip { 255 - v } else { v };
+            Rgb([v, v, v])
+        }))
+    }
+
+    #[test]
+    fn test_dhash_survives_resizing() {
+        let original = dhash(&gradient(800, 600, false));
+        let resized = dhash(&gradient(200, 150, false));
+        let different = dhash(&gradient(800, 600, true));
+
+        assert!(hamming_distance(original, resized) <= DEFAULT_MAX_DISTANCE);
+        assert!(hamming_distance(original, different) > DEFAULT_MAX_DISTANCE);
+        assert_eq!(parse_hash(&format_hash(original)), Some(original));
+    }
+
+    #[test]
+    fn test_group_similar() {
+        let with_hash = |path: &str, hash: u64| {
+            let mut file = FileMetadata::for_test(path, path, 10, FileType::Image);
+            file.perceptual_hash = Some(format_hash(hash));
+            file
+        };
+        let files = vec![
+            with_hash("/p/a.jpg", 0xFFFF_0000_FFFF_0000),
+            with_hash("/p/b.jpg", 0x0F0F_0F0F_0F0F_0F0F),
+            with_hash("/p/a-small.jpg", 0xFFFF_0000_FFFF_0003),
+            with_hash("/p/a-edit.jpg", 0xFFFF_0000_FFFF_000F),
+        ];
+
+        let groups = group_similar(&files, 2);
+        assert_eq!(groups.len(), 1);
+        let paths: Vec<&str> = groups[0].files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["/p/a.jpg", "/p/a-small.jpg", "/p/a-edit.jpg"]);
+        assert_eq!(groups[0].max_distance, 2);
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, FixedOffset, Utc};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FileType {
@@ -11,6 +11,13 @@ pub enum FileType {
     Other,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Exposure {
+    Good,
+    Under,      // Mostly crushed shadows
+    Over,       // Mostly blown highlights
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMetadata {
     pub path: String,
@@ -22,22 +29,283 @@ pub struct FileMetadata {
     
     // Optional metadata (depends on file type)
     pub date_taken: Option<DateTime<Utc>>,  // For photos/videos
+    #[serde(default)]
+    pub date_taken_offset: Option<i32>,     // Minutes east of UTC where it was taken
+    #[serde(default)]
+    pub date_source: Option<DateSource>,    // Where date_taken came from
     pub width: Option<u32>,                  // For images/videos
     pub height: Option<u32>,                 // For images/videos
     pub duration: Option<u32>,               // For videos/audio (seconds)
     pub page_count: Option<u32>,             // For documents
     
-    pub hash: String,
+    pub hash: Option<String>,               // SHA-256; None when no other file could be a copy
+    #[serde(default)]
+    pub perceptual_hash: Option<String>,    // dHash as 16 hex digits (images only)
+    #[serde(default)]
+    pub sharpness: Option<f32>,             // Variance of the Laplacian (images only)
+    #[serde(default)]
+    pub exposure: Option<Exposure>,
+    #[serde(default)]
+    pub quality_score: Option<f32>,         // 0-1, sharpness and exposure combined
     pub is_screenshot: bool,
+    #[serde(default)]
+    pub screenshot_confidence: Option<f32>, // 0-1, images only
+    #[serde(default)]
+    pub screenshot_reasons: Vec<String>,    // Signals behind the confidence
+    #[serde(default)]
+    pub is_likely_download: bool,           // Meme, saved image or messaging-app copy
+    #[serde(default)]
+    pub download_rules: Vec<String>,        // Download rules that fired, e.g. "folder:WhatsApp Images"
     pub is_duplicate: bool,
+    #[serde(default)]
+    pub content_identifier: Option<String>, // Apple Live Photo key, shared by the still and its MOV
+    #[serde(default)]
+    pub paired_with: Option<String>,        // Other half of a Live Photo
+    #[serde(default)]
+    pub motion_photo: bool,                 // JPEG with an embedded video (Google, Samsung)
+    #[serde(default)]
+    pub companions: Vec<String>,            // RAW half and sidecars that travel with this file
+    #[serde(default)]
+    pub companion_of: Option<String>,       // Lead file of a RAW+JPEG pair, set on the RAW
+    #[serde(default)]
+    pub archive: Option<ArchiveStamp>,      // Size and mtime of the archive an entry was read from
     
     // Media-specific (for backward compatibility)
     pub camera_make: Option<String>,
     pub camera_model: Option<String>,
+
+    // Camera settings from EXIF
+    #[serde(default)]
+    pub lens_model: Option<String>,
+    #[serde(default)]
+    pub focal_length: Option<f32>,           // Millimetres
+    #[serde(default)]
+    pub iso: Option<u32>,
+    #[serde(default)]
+    pub aperture: Option<f32>,               // f-number
+    #[serde(default)]
+    pub exposure_time: Option<f32>,          // Shutter speed (seconds)
+    #[serde(default)]
+    pub flash_fired: Option<bool>,
+    #[serde(default)]
+    pub orientation: Option<u16>,            // EXIF orientation (1-8)
+
+    // Location from EXIF GPS
+    #[serde(default)]
+    pub latitude: Option<f64>,               // Decimal degrees, south is negative
+    #[serde(default)]
+    pub longitude: Option<f64>,              // Decimal degrees, west is negative
+    #[serde(default)]
+    pub altitude: Option<f64>,               // Metres, below sea level is negative
+
+    // Content sniffing
+    #[serde(default)]
+    pub detected_format: Option<String>,     // Format recognised from magic bytes
+    #[serde(default)]
+    pub extension_mismatch: bool,            // Content disagrees with the extension
+}
+
+impl FileMetadata {
+    /// Capture time on the local clock where it was taken, when the offset is known
+    pub fn local_date_taken(&self) -> Option<DateTime<FixedOffset>> {
+        let offset = FixedOffset::east_opt(self.date_taken_offset? * 60)?;
+        Some(self.date_taken?.with_timezone(&offset))
+    }
+}
+
+#[cfg(test)]
+impl FileMetadata {
+    /// Minimal metadata record for unit tests
+    pub fn for_test(path: &str, hash: &str, file_size: u64, file_type: FileType) -> Self {
+        Self {
+            path: path.to_string(),
+            file_name: std::path::Path::new(path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            file_size,
+            file_type,
+            created_at: None,
+            modified_at: None,
+            date_taken: None,
+            date_taken_offset: None,
+            date_source: None,
+            width: None,
+            height: None,
+            duration: None,
+            page_count: None,
+            hash: Some(hash.to_string()),
+            perceptual_hash: None,
+            sharpness: None,
+            exposure: None,
+            quality_score: None,
+            is_screenshot: false,
+            screenshot_confidence: None,
+            screenshot_reasons: Vec::new(),
+            is_likely_download: false,
+            download_rules: Vec::new(),
+            is_duplicate: false,
+            content_identifier: None,
+            paired_with: None,
+            motion_photo: false,
+            companions: Vec::new(),
+            companion_of: None,
+            archive: None,
+            camera_make: None,
+            camera_model: None,
+            lens_model: None,
+            focal_length: None,
+            iso: None,
+            aperture: None,
+            exposure_time: None,
+            flash_fired: None,
+            orientation: None,
+            latitude: None,
+            longitude: None,
+            altitude: None,
+            detected_format: None,
+            extension_mismatch: false,
+        }
+    }
+}
+
+/// An archive as it was when its entries were cataloged, so a rescan can tell
+/// whether it needs reading again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveStamp {
+    pub file_size: u64,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraInfo {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens_model: Option<String>,
+    pub focal_length: Option<f32>,
+    pub iso: Option<u32>,
+    pub aperture: Option<f32>,
+    pub exposure_time: Option<f32>,
+    pub flash_fired: Option<bool>,
+    pub orientation: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GpsCoordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExifField {
+    pub tag: String,
+    pub ifd: String,        // "primary" or "thumbnail"
+    pub value: String,      // Human-readable, with units
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDetails {
+    pub metadata: Option<FileMetadata>,     // Catalog entry, if the file has been scanned
+    pub exif: Vec<ExifField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarGroup {
+    pub files: Vec<FileMetadata>,
+    pub max_distance: u32,          // Largest Hamming distance between linked members
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KeeperRule {
+    HighestResolution,
+    EarliestDate,
+    HasExif,
+    PreferredFolder(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DuplicateKind {
+    Exact,      // Byte-identical (same SHA-256)
+    Similar,    // Perceptually near-identical, e.g. a re-compressed copy
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    pub keeper: String,             // Suggested file to keep; the rest are marked is_duplicate
+    pub files: Vec<FileMetadata>,   // Keeper first, then the others in keeper-rule order
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ResolutionMethod {
+    Trash,          // Send non-keepers to the OS trash
+    Quarantine,     // Move them into a quarantine folder with a manifest
+    Hardlink,       // Replace exact copies with hardlinks to the keeper
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedFile {
+    pub path: String,
+    pub keeper: String,
+    pub file_size: u64,
+    pub stored_path: Option<String>,    // Where a quarantined file now lives
+    pub status: OperationStatus,
+    pub error_message: Option<String>,
+    pub restored: bool,
+}
+
+/// Journal entry for one duplicate resolution, kept in the catalog so it can be undone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resolution {
+    pub id: String,
+    pub method: ResolutionMethod,
+    pub resolved_at: DateTime<Utc>,
+    pub quarantine_dir: Option<String>,
+    pub files: Vec<ResolvedFile>,
+    pub reclaimed_bytes: u64,
+    pub undone_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BurstFrame {
+    pub file: FileMetadata,
+    pub sharpness: Option<f64>,     // Variance of the Laplacian; None if it couldn't be decoded
+}
+
+/// Photos shot in quick succession of the same scene
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Burst {
+    pub camera_model: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub frames: Vec<BurstFrame>,    // In capture order
+    pub sharpest: Option<String>,   // Path of the frame to keep
+}
+
+/// Where a file's `date_taken` came from, most trustworthy first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DateSource {
+    Exif,
+    Container,      // Video metadata (MP4/MOV)
+    Takeout,        // Google Takeout JSON sidecar
+    Xmp,            // XMP sidecar
+    FileName,
+    Folder,
+    Modified,       // Filesystem mtime, usually the copy or download date
+}
+
+/// How much of each file the scanner hashes
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum HashMode {
+    #[default]
+    Full,           // SHA-256 of every file
+    Prefilter,      // Bucket by size and partial hash; fully hash only possible duplicates
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScanProgress {
+    pub session_id: String,
     pub total_files: usize,
     pub processed_files: usize,
     pub current_file: String,
@@ -54,6 +322,12 @@ pub struct FileTypeStats {
     pub other: usize,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocationStats {
+    pub with_location: usize,        // Images with GPS coordinates
+    pub without_location: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QualityIssues {
     pub screenshots: usize,
@@ -61,7 +335,10 @@ pub struct QualityIssues {
     pub low_resolution: usize,      // Images below 1080p
     pub small_files: usize,          // Compressed/low quality (< 500KB)
     pub missing_metadata: usize,     // No EXIF date
-    pub potential_memes: usize,      // Suspicious filenames
+    pub potential_memes: usize,      // Flagged by the download rules
+    pub extension_mismatches: usize, // Content doesn't match extension
+    pub blurry: usize,               // Laplacian variance below the blur threshold
+    pub badly_exposed: usize,        // Under- or overexposed
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,13 +350,70 @@ pub struct ScanStats {
     pub total_size: u64,
     pub date_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
     pub quality: QualityIssues,
+    pub location: LocationStats,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScanResult {
+    pub session_id: String,
     pub root_path: String,              // The scanned folder path
     pub files: Vec<FileMetadata>,       // Changed from 'photos'
     pub stats: ScanStats,
+    pub changes: Option<ScanChanges>,   // Only set for complete incremental scans
+    pub complete: bool,                 // False if the scan was cancelled part-way
+    pub errors: Vec<ScanError>,         // Files and folders that couldn't be read in full
+}
+
+/// Scan pipeline step where a file ran into trouble
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ScanStage {
+    Walk,       // Listing a directory
+    Metadata,   // Reading size and dates
+    Hash,
+    Exif,
+    Decode,     // Reading pixels or dimensions
+    Archive,    // Listing or unpacking a ZIP or TAR
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ScanErrorKind {
+    NotFound,
+    PermissionDenied,
+    Corrupt,
+    Unsupported,
+    Io,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanError {
+    pub path: String,
+    pub stage: ScanStage,
+    pub kind: ScanErrorKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMove {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanChanges {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    pub moved: Vec<FileMove>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanRecord {
+    pub id: i64,
+    pub root_path: String,
+    pub scanned_at: DateTime<Utc>,
+    pub total_files: usize,
+    pub total_size: u64,
 }
 
 // ============================================================================
@@ -107,10 +441,12 @@ pub struct FolderPreview {
     pub file_count: usize,
     pub total_size: u64,
     pub files: Vec<String>, // File names that will go here
+    pub sources: Vec<String>, // Where each of those files is now, in the same order
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrganizationPlan {
+    pub source_root: String,        // Scanned folder the files are loaded from
     pub destination_root: String,
     pub strategy: OrganizationStrategy,
     pub mode: OperationMode,
@@ -20,18 +20,57 @@ export interface FileMetadata {
 	
 	// Optional metadata (depends on file type)
 	date_taken?: string;
+	date_taken_offset?: number;  // Minutes east of UTC where it was taken
+	date_source?: 'Exif' | 'Container' | 'Takeout' | 'Xmp' | 'FileName' | 'Folder' | 'Modified';
 	width?: number;
 	height?: number;
 	duration?: number;      // For videos/audio (seconds)
 	page_count?: number;    // For documents
 	
-	hash: string;
+	hash?: string;          // Only computed for files that could be duplicates
+	perceptual_hash?: string; // dHash as 16 hex digits (images only)
+	sharpness?: number;     // Variance of the Laplacian (images only)
+	exposure?: 'Good' | 'Under' | 'Over';
+	quality_score?: number; // 0-1, sharpness and exposure combined
+	content_identifier?: string; // Apple Live Photo key, shared by the still and its MOV
+	paired_with?: string;   // Other half of a Live Photo
+	motion_photo?: boolean; // JPEG with an embedded video
+	companions?: string[];  // RAW half and sidecars that travel with this file
+	companion_of?: string;  // Lead file of a RAW+JPEG pair
+	archive?: ArchiveStamp; // Size and mtime of the archive an entry was read from
 	is_screenshot: boolean;
+	screenshot_confidence?: number; // 0-1, images only
+	screenshot_reasons?: string[];  // Signals behind the confidence
+	is_likely_download?: boolean;   // Meme, saved image or messaging-app copy
+	download_rules?: string[];      // Download rules that fired, e.g. "folder:WhatsApp Images"
 	is_duplicate: boolean;
 	
 	// Media-specific
 	camera_make?: string;
 	camera_model?: string;
+	
+	// Camera settings from EXIF
+	lens_model?: string;
+	focal_length?: number;        // Millimetres
+	iso?: number;
+	aperture?: number;            // f-number
+	exposure_time?: number;       // Shutter speed (seconds)
+	flash_fired?: boolean;
+	orientation?: number;         // EXIF orientation (1-8)
+	
+	// Location from EXIF GPS
+	latitude?: number;            // Decimal degrees, south is negative
+	longitude?: number;           // Decimal degrees, west is negative
+	altitude?: number;            // Metres, below sea level is negative
+	
+	// Content sniffing
+	detected_format?: string;     // Format recognised from magic bytes
+	extension_mismatch?: boolean; // Content disagrees with the extension
+}
+
+export interface ArchiveStamp {
+	file_size: number;
+	modified_at?: string;
 }
 
 export interface FileTypeStats {
@@ -43,6 +82,23 @@ export interface FileTypeStats {
 	other: number;
 }
 
+export interface QualityIssues {
+	screenshots: number;
+	duplicates: number;
+	low_resolution: number;       // Images below 1080p
+	small_files: number;          // Compressed/low quality (< 500KB)
+	missing_metadata: number;     // No EXIF date
+	potential_memes: number;      // Flagged by the download rules
+	extension_mismatches: number; // Content doesn't match extension
+	blurry: number;               // Laplacian variance below the blur threshold
+	badly_exposed: number;        // Under- or overexposed
+}
+
+export interface LocationStats {
+	with_location: number;        // Images with GPS coordinates
+	without_location: number;
+}
+
 export interface ScanStats {
 	total_files: number;
 	file_types: FileTypeStats;
@@ -51,11 +107,36 @@ export interface ScanStats {
 	total_size: number;
 	date_range_start?: string;
 	date_range_end?: string;
+	quality: QualityIssues;
+	location: LocationStats;
 }
 
 export interface ScanResult {
+	session_id: string;
 	files: FileMetadata[];  // Changed from 'photos'
 	stats: ScanStats;
+	changes?: ScanChanges;  // Only set for complete incremental scans
+	complete: boolean;      // False if the scan was cancelled part-way
+	errors?: ScanError[];   // Files and folders that couldn't be read in full
+}
+
+export interface FileMove {
+	from: string;
+	to: string;
+}
+
+export interface ScanChanges {
+	added: string[];
+	changed: string[];
+	removed: string[];
+	moved: FileMove[];
+}
+
+export interface ScanError {
+	path: string;
+	stage: 'Walk' | 'Metadata' | 'Hash' | 'Exif' | 'Decode' | 'Archive';
+	kind: 'NotFound' | 'PermissionDenied' | 'Corrupt' | 'Unsupported' | 'Io' | 'Other';
+	message: string;
 }
 
 // Get initial value from localStorage if in browser
@@ -75,9 +75,11 @@ export interface FolderPreview {
 	file_count: number;
 	total_size: number;
 	files: string[]; // File names that will go here
+	sources: string[]; // Where each of those files is now, in the same order
 }
 
 export interface OrganizationPlan {
+	source_root: string; // Scanned folder the files are loaded from
 	destination_root: string;
 	strategy: OrganizationStrategy;
 	mode: OperationMode;